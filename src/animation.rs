@@ -0,0 +1,211 @@
+use crate::*;
+use bevy::asset::LoadContext;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlayerAnimation {
+    Idle,
+    Run,
+    Jump,
+    Fall,
+    Land,
+    Crouch,
+    Hit,
+    Death,
+}
+
+impl PlayerAnimation {
+    /// Whether the clip loops instead of playing once.
+    fn repeats(&self) -> bool {
+        matches!(
+            self,
+            PlayerAnimation::Idle
+                | PlayerAnimation::Run
+                | PlayerAnimation::Fall
+                | PlayerAnimation::Crouch
+        )
+    }
+}
+
+/// A clip of the player model.
+#[derive(Deserialize, Clone, Debug)]
+pub enum ClipRef {
+    Name(String),
+    Index(usize),
+}
+
+/// Which clips the player plays, as written in an `.animations.ron` file.
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct AnimationConfig {
+    pub clips: HashMap<PlayerAnimation, ClipRef>,
+    /// Seconds to cross-fade from one clip to the next.
+    pub transition: f32,
+    /// Horizontal speed from which the player runs instead of idling.
+    pub run_speed: Scalar,
+    /// Seconds the land clip plays for after touching the ground.
+    pub land_duration: f32,
+    /// Seconds the hit clip plays for after being hit.
+    pub hit_duration: f32,
+}
+
+impl RonAsset for AnimationConfig {
+    type File = Self;
+
+    fn from_file(file: Self, _load_context: &mut LoadContext) -> Self {
+        file
+    }
+}
+
+/// The clips found in the player model for every animation state.
+#[derive(Resource, Default)]
+struct PlayerClips(HashMap<PlayerAnimation, Handle<AnimationClip>>);
+
+/// Picks the animation of the player from the state of its controller.
+#[derive(Component, Default)]
+pub struct AnimationController {
+    current: Option<PlayerAnimation>,
+    was_grounded: bool,
+    /// Seconds left of the land clip.
+    landing: f32,
+    /// Seconds left of the hit clip.
+    hit: f32,
+}
+
+pub struct PlayerAnimationPlugin;
+
+impl Plugin for PlayerAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimationConfig>()
+            .register_asset_loader(RonAssetLoader::<AnimationConfig>::new(&["animations.ron"]))
+            .init_resource::<PlayerClips>()
+            .add_systems(OnExit(GameState::AssetLoading), find_player_clips)
+            .add_systems(
+                Update,
+                (start_hit_animation, animate_player)
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+fn find_player_clips(
+    config: Res<AssetPlayerAnimations>,
+    configs: Res<Assets<AnimationConfig>>,
+    animations: Res<Animations>,
+    named_animations: Res<NamedAnimations>,
+    mut clips: ResMut<PlayerClips>,
+) {
+    let Some(config) = configs.get(&config.0) else {
+        return;
+    };
+    for (&animation, clip) in &config.clips {
+        let handle = match clip {
+            ClipRef::Name(name) => named_animations.0.get(name),
+            ClipRef::Index(index) => animations.0.get(*index),
+        };
+        match handle {
+            Some(handle) => {
+                clips.0.insert(animation, handle.clone());
+            }
+            None => warn!("the player model has no {clip:?} clip for {animation:?}"),
+        }
+    }
+}
+
+fn start_hit_animation(
+    config: Res<AssetPlayerAnimations>,
+    configs: Res<Assets<AnimationConfig>>,
+    mut query: Query<&mut AnimationController, Added<Invulnerable>>,
+) {
+    let Some(config) = configs.get(&config.0) else {
+        return;
+    };
+    for mut controller in &mut query {
+        controller.hit = config.hit_duration;
+    }
+}
+
+fn animate_player(
+    time: Res<Time>,
+    config: Res<AssetPlayerAnimations>,
+    configs: Res<Assets<AnimationConfig>>,
+    clips: Res<PlayerClips>,
+    mut players: Query<(
+        Entity,
+        &mut AnimationController,
+        &LinearVelocity,
+        &Health,
+        Has<Grounded>,
+        Has<Crouching>,
+    )>,
+    mut anim_players: Query<(Entity, &mut AnimationPlayer)>,
+    parents: Query<&Parent>,
+) {
+    let Some(config) = configs.get(&config.0) else {
+        return;
+    };
+    let delta = time.delta_seconds();
+
+    for (player, mut controller, linear_velocity, health, is_grounded, is_crouching) in &mut players
+    {
+        if is_grounded && !controller.was_grounded {
+            controller.landing = config.land_duration;
+        }
+        controller.was_grounded = is_grounded;
+        controller.landing = (controller.landing - delta).max(0.);
+        controller.hit = (controller.hit - delta).max(0.);
+
+        let animation = if health.is_dead() {
+            PlayerAnimation::Death
+        } else if controller.hit > 0. {
+            PlayerAnimation::Hit
+        } else if is_crouching {
+            PlayerAnimation::Crouch
+        } else if !is_grounded && linear_velocity.y > 0. {
+            PlayerAnimation::Jump
+        } else if !is_grounded {
+            PlayerAnimation::Fall
+        } else if controller.landing > 0. {
+            PlayerAnimation::Land
+        } else if linear_velocity.x.abs() > config.run_speed {
+            PlayerAnimation::Run
+        } else {
+            PlayerAnimation::Idle
+        };
+        // Keep the previous clip for states the model has no clip for, but
+        // start with the run clip rather than nothing
+        let animation = match controller.current {
+            None if !clips.0.contains_key(&animation) => PlayerAnimation::Run,
+            _ => animation,
+        };
+        if controller.current == Some(animation) {
+            continue;
+        }
+        let Some(clip) = clips.0.get(&animation) else {
+            continue;
+        };
+
+        // The animation player is somewhere in the model's scene
+        let Some((_, mut anim_player)) = anim_players
+            .iter_mut()
+            .find(|(entity, _)| parents.iter_ancestors(*entity).any(|e| e == player))
+        else {
+            continue;
+        };
+        // Cross-fading needs a clip to fade from
+        if controller.current.is_some() {
+            anim_player.play_with_transition(
+                clip.clone_weak(),
+                Duration::from_secs_f32(config.transition),
+            );
+        } else {
+            anim_player.play(clip.clone_weak());
+        }
+        if animation.repeats() {
+            anim_player.repeat();
+        }
+        controller.current = Some(animation);
+    }
+}
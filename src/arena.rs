@@ -0,0 +1,227 @@
+use crate::*;
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::render::camera::{CameraProjection, ScalingMode};
+use bevy::window::PrimaryWindow;
+
+/// How the cameras fit the window: always showing at least
+/// [`constants::WIDTH`] by [`constants::HEIGHT`], and more on windows with
+/// another aspect ratio.
+pub const SCALING_MODE: ScalingMode = ScalingMode::AutoMin {
+    min_width: constants::WIDTH,
+    min_height: constants::HEIGHT,
+};
+
+/// The part of the `z = 0` plane seen by the game camera, where the player
+/// moves and enemies fall. It follows the window as it is resized.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct ArenaBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Default for ArenaBounds {
+    fn default() -> Self {
+        let half_size = Vec2::new(constants::WIDTH, constants::HEIGHT) / 2.;
+        Self {
+            min: -half_size,
+            max: half_size,
+        }
+    }
+}
+
+impl ArenaBounds {
+    pub fn width(&self) -> f32 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max.y - self.min.y
+    }
+
+    /// Whether `point` is more than `margin` past the sides or the bottom of
+    /// the arena. Things above it are still falling in.
+    pub fn has_left(&self, point: Vec2, margin: f32) -> bool {
+        point.x < self.min.x - margin
+            || point.x > self.max.x + margin
+            || point.y < self.min.y - margin
+    }
+}
+
+/// The camera the arena is seen through.
+#[derive(Component)]
+pub struct GameCamera;
+
+/// The invisible walls keeping the player in the arena.
+#[derive(Component, Clone, Copy, Debug)]
+pub enum Wall {
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Wall {
+    pub const ALL: [Wall; 3] = [Wall::Bottom, Wall::Left, Wall::Right];
+
+    /// Where the wall goes for the given bounds, and its collider.
+    pub fn placement(&self, bounds: &ArenaBounds) -> (Transform, Collider) {
+        let center_y = (bounds.min.y + bounds.max.y) / 2.;
+        let (translation, collider) = match self {
+            // A bit below the bottom of the screen
+            Wall::Bottom => (
+                Vec3::new(0., bounds.min.y - 1., 0.),
+                Collider::cuboid(bounds.width(), 0.002, 8.0),
+            ),
+            Wall::Left => (
+                Vec3::new(bounds.min.x + 0.5, center_y, 0.),
+                Collider::cuboid(bounds.height(), 0.002, 8.0),
+            ),
+            Wall::Right => (
+                Vec3::new(bounds.max.x - 0.5, center_y, 0.),
+                Collider::cuboid(bounds.height(), 0.002, 8.0),
+            ),
+        };
+        let mut transform = Transform::from_translation(translation);
+        if !matches!(self, Wall::Bottom) {
+            transform.rotate_z(PI / 2.0);
+        }
+        (transform, collider)
+    }
+}
+
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ArenaBounds>()
+            // The bounds are needed to place the player and the walls, and
+            // are computed from the camera once it is actually spawned
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (spawn_game_camera, apply_deferred, update_arena_bounds)
+                    .chain()
+                    .before(setup),
+            )
+            .add_systems(
+                PreUpdate,
+                update_arena_bounds.run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                Update,
+                place_walls
+                    .run_if(resource_changed::<ArenaBounds>())
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+fn spawn_game_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            projection: OrthographicProjection {
+                scaling_mode: SCALING_MODE,
+                ..default()
+            }
+            .into(),
+            camera_3d: Camera3d {
+                // don't clear the color while rendering this camera
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 3.0, 15.0).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        },
+        GameCamera,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+/// Projects the corners of what the game camera shows onto the `z = 0` plane.
+///
+/// This works from the window size rather than from the camera's computed
+/// viewport, so the bounds are right as soon as the camera is spawned.
+fn update_arena_bounds(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Projection, &Transform), With<GameCamera>>,
+    mut bounds: ResMut<ArenaBounds>,
+) {
+    let (Ok(window), Ok((Projection::Orthographic(projection), transform))) =
+        (windows.get_single(), cameras.get_single())
+    else {
+        return;
+    };
+    if window.width() <= 0. || window.height() <= 0. {
+        return;
+    }
+    let mut projection = projection.clone();
+    projection.update(window.width(), window.height());
+
+    // Orthographic rays all go the same way, from the camera's near plane
+    let direction = transform.forward();
+    if direction.z == 0. {
+        return;
+    }
+    let corner = |view_position: Vec2| {
+        let origin = transform.transform_point(view_position.extend(0.));
+        (origin - direction * (origin.z / direction.z)).truncate()
+    };
+    let bottom_left = corner(projection.area.min);
+    let top_right = corner(projection.area.max);
+    bounds.set_if_neq(ArenaBounds {
+        min: bottom_left.min(top_right),
+        max: bottom_left.max(top_right),
+    });
+}
+
+fn place_walls(bounds: Res<ArenaBounds>, mut walls: Query<(&Wall, &mut Transform, &mut Collider)>) {
+    for (wall, mut transform, mut collider) in &mut walls {
+        (*transform, *collider) = wall.placement(&bounds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::headless_app;
+    use bevy::window::WindowResolution;
+
+    #[test]
+    fn bounds_follow_the_camera_spawned_in_the_same_frame() {
+        let mut app = headless_app();
+        app.init_resource::<ArenaBounds>().add_systems(
+            Update,
+            (spawn_game_camera, apply_deferred, update_arena_bounds).chain(),
+        );
+        // Wider than the 16:9 the default bounds are for
+        app.world.spawn((
+            Window {
+                resolution: WindowResolution::new(1600., 720.),
+                ..default()
+            },
+            PrimaryWindow,
+        ));
+
+        app.update();
+
+        let bounds = *app.world.resource::<ArenaBounds>();
+        assert_ne!(bounds, ArenaBounds::default());
+        assert!(bounds.width() > constants::WIDTH);
+    }
+
+    #[test]
+    fn has_left_past_the_sides_and_bottom() {
+        let bounds = ArenaBounds {
+            min: Vec2::new(-8., -4.),
+            max: Vec2::new(8., 4.),
+        };
+        assert!(!bounds.has_left(Vec2::ZERO, 1.));
+        assert!(!bounds.has_left(Vec2::new(-8.9, 0.), 1.));
+        assert!(bounds.has_left(Vec2::new(-9.1, 0.), 1.));
+        assert!(!bounds.has_left(Vec2::new(8.9, 0.), 1.));
+        assert!(bounds.has_left(Vec2::new(9.1, 0.), 1.));
+        assert!(!bounds.has_left(Vec2::new(0., -4.9), 1.));
+        assert!(bounds.has_left(Vec2::new(0., -5.1), 1.));
+        // Above the arena is where enemies come from
+        assert!(!bounds.has_left(Vec2::new(0., 100.), 1.));
+        assert!(bounds.has_left(Vec2::new(-8.5, 0.), 0.));
+    }
+}
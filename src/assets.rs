@@ -1,34 +1,96 @@
-use crate::GameState;
+use crate::{AnimationConfig, Biomes, EnemyArchetypes, GameState, InputMap};
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
 use bevy::gltf::Gltf;
 use bevy::prelude::*;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use thiserror::Error;
 
 #[derive(Resource)]
-pub struct AssetBackground(pub Handle<Image>);
+pub struct AssetBiomes(pub Handle<Biomes>);
 
 #[derive(Resource)]
 pub struct Animations(pub Vec<Handle<AnimationClip>>);
 
+/// The player animations, by their name in the model file.
 #[derive(Resource)]
-pub struct PlayerModel(pub Handle<Scene>);
+pub struct NamedAnimations(pub HashMap<String, Handle<AnimationClip>>);
 
 #[derive(Resource)]
-pub struct EnemyModel {
-    pub rojo: Handle<Scene>,
-    pub amarillo: Handle<Scene>,
-}
+pub struct PlayerModel(pub Handle<Scene>);
 
 #[derive(Resource)]
 struct AssetPackPlayer(Handle<Gltf>);
 
 #[derive(Resource)]
-struct AssetPackEnemy {
-    rojo: Handle<Gltf>,
-    amarillo: Handle<Gltf>,
-}
+pub struct AssetEnemies(pub Handle<EnemyArchetypes>);
 
 #[derive(Resource)]
 pub struct AssetOST(pub Handle<AudioSource>);
 
+#[derive(Resource)]
+pub struct AssetPlayerAnimations(pub Handle<AnimationConfig>);
+
+#[derive(Resource)]
+pub struct AssetInputMap(pub Handle<InputMap>);
+
+/// An asset written as a RON file.
+pub trait RonAsset: Asset {
+    /// What the file deserializes to.
+    type File: DeserializeOwned + Send;
+
+    /// Builds the asset from its file, loading any assets it refers to.
+    fn from_file(file: Self::File, load_context: &mut LoadContext) -> Self;
+}
+
+#[derive(Debug, Error)]
+pub enum RonAssetLoaderError {
+    #[error("could not read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+/// Loads a [`RonAsset`] from files with the given extensions.
+pub struct RonAssetLoader<T> {
+    extensions: &'static [&'static str],
+    asset: PhantomData<fn() -> T>,
+}
+
+impl<T> RonAssetLoader<T> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            asset: PhantomData,
+        }
+    }
+}
+
+impl<T: RonAsset> AssetLoader for RonAssetLoader<T> {
+    type Asset = T;
+    type Settings = ();
+    type Error = RonAssetLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let file: T::File = ron::de::from_bytes(&bytes)?;
+            Ok(T::from_file(file, load_context))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
+
 pub struct AssetLoaderPlugin;
 
 impl Plugin for AssetLoaderPlugin {
@@ -36,12 +98,10 @@ impl Plugin for AssetLoaderPlugin {
         app.add_systems(Startup, load_assets).add_systems(
             Update,
             (
-                load_gltf_enemy.run_if(in_state(GameState::AssetLoading)),
                 load_gltf_player.run_if(in_state(GameState::AssetLoading)),
                 check_if_loaded
                     .run_if(in_state(GameState::AssetLoading))
-                    .after(load_gltf_player)
-                    .after(load_gltf_enemy),
+                    .after(load_gltf_player),
             ),
         );
     }
@@ -49,14 +109,17 @@ impl Plugin for AssetLoaderPlugin {
 
 fn load_assets(mut commands: Commands, server: Res<AssetServer>) {
     let run: Handle<Gltf> = server.load("run.glb");
-    let amarillo: Handle<Gltf> = server.load("frijol_amarillo.glb");
-    let rojo: Handle<Gltf> = server.load("frijol_rojo.glb");
-    let background: Handle<Image> = server.load("Background.png");
+    let enemies: Handle<EnemyArchetypes> = server.load("beans.enemies.ron");
+    let biomes: Handle<Biomes> = server.load("run.biomes.ron");
     let ost: Handle<AudioSource> = server.load("ost.flac");
+    let player_animations: Handle<AnimationConfig> = server.load("player.animations.ron");
+    let input_map: Handle<InputMap> = server.load("default.input.ron");
     commands.insert_resource(AssetPackPlayer(run));
-    commands.insert_resource(AssetPackEnemy { rojo, amarillo });
-    commands.insert_resource(AssetBackground(background));
+    commands.insert_resource(AssetEnemies(enemies));
+    commands.insert_resource(AssetBiomes(biomes));
     commands.insert_resource(AssetOST(ost));
+    commands.insert_resource(AssetPlayerAnimations(player_animations));
+    commands.insert_resource(AssetInputMap(input_map));
 }
 
 fn load_gltf_player(
@@ -67,41 +130,38 @@ fn load_gltf_player(
     if let Some(gltf) = assets_gltf.get(&my.0) {
         commands.insert_resource(PlayerModel(gltf.scenes[0].clone()));
         commands.insert_resource(Animations(gltf.animations.clone()));
-    }
-}
-
-fn load_gltf_enemy(
-    mut commands: Commands,
-    my: Res<AssetPackEnemy>,
-    assets_gltf: Res<Assets<Gltf>>,
-) {
-    if let Some(gltf_rojo) = assets_gltf.get(&my.rojo) {
-        if let Some(gltf_amarillo) = assets_gltf.get(&my.amarillo) {
-            commands.insert_resource(EnemyModel {
-                rojo: gltf_rojo.scenes[0].clone(),
-                amarillo: gltf_amarillo.scenes[0].clone(),
-            });
-        }
+        commands.insert_resource(NamedAnimations(gltf.named_animations.clone()));
     }
 }
 
 fn check_if_loaded(
+    server: Res<AssetServer>,
     player: Res<AssetPackPlayer>,
-    enemy: Res<AssetPackEnemy>,
-    background: Res<AssetBackground>,
+    enemies: Res<AssetEnemies>,
+    biomes: Res<AssetBiomes>,
     ost: Res<AssetOST>,
+    player_animations: Res<AssetPlayerAnimations>,
+    input_map: Res<AssetInputMap>,
     assets_gltf: Res<Assets<Gltf>>,
-    assets_img: Res<Assets<Image>>,
     assets_audio: Res<Assets<AudioSource>>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
     let player_loaded = assets_gltf.get(&player.0).is_some();
-    let enemy_loaded =
-        assets_gltf.get(&enemy.rojo).is_some() && assets_gltf.get(&enemy.amarillo).is_some();
-    let image_loaded = assets_img.get(&background.0).is_some();
+    // Enemy models are loaded as dependencies of their archetypes
+    let enemy_loaded = server.is_loaded_with_dependencies(&enemies.0);
+    // Backgrounds and their textures are loaded as dependencies of the biomes
+    let biomes_loaded = server.is_loaded_with_dependencies(&biomes.0);
     let ost_loaded = assets_audio.get(&ost.0).is_some();
+    let animations_loaded = server.is_loaded_with_dependencies(&player_animations.0);
+    let input_map_loaded = server.is_loaded_with_dependencies(&input_map.0);
 
-    if player_loaded && enemy_loaded && image_loaded && ost_loaded {
-        next_state.set(GameState::InGame);
+    if player_loaded
+        && enemy_loaded
+        && biomes_loaded
+        && ost_loaded
+        && animations_loaded
+        && input_map_loaded
+    {
+        next_state.set(GameState::Menu);
     }
 }
@@ -1,12 +1,21 @@
 use crate::*;
 use bevy::audio::PlaybackMode;
 
-pub fn play_ost(ost: Res<OST>, mut commands: Commands) {
-    commands.spawn(AudioBundle {
-        source: ost.0.clone(),
-        settings: PlaybackSettings {
-            mode: PlaybackMode::Loop,
-            ..default()
+/// Marks the looping soundtrack.
+#[derive(Component)]
+pub struct Music;
+
+pub fn play_ost(ost: Res<OST>, settings: Res<Settings>, mut commands: Commands) {
+    commands.spawn((
+        AudioBundle {
+            source: ost.0.clone(),
+            settings: PlaybackSettings {
+                mode: PlaybackMode::Loop,
+                volume: settings.music_volume(),
+                ..default()
+            },
         },
-    });
+        Music,
+        StateScoped(GameState::InGame),
+    ));
 }
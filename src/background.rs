@@ -1,99 +1,279 @@
-use crate::constants;
 use crate::*;
+use bevy::asset::LoadContext;
 use bevy::prelude::*;
-use bevy::render::camera::ScalingMode;
-use itertools::Itertools;
+use serde::Deserialize;
 
 #[derive(Component)]
-pub struct Velocity(f32);
+pub struct Background;
 
+/// One tile of a parallax layer, wrapped back to the right once it leaves the
+/// screen on the left.
 #[derive(Component)]
-pub struct Background;
+pub struct ParallaxTile {
+    /// Scroll speed relative to the [`WorldSpeed`].
+    speed: f32,
+    /// Width of the whole layer, which is how far a tile jumps when wrapping.
+    layer_width: f32,
+    /// The background this tile is part of.
+    background: AssetId<ParallaxBackground>,
+}
 
+/// A tile of a new background fading in over the previous one.
+#[derive(Component)]
+pub struct FadeIn(Timer);
+
+/// The background currently shown. Changing it cross-fades to the new one.
 #[derive(Resource)]
-pub struct BackgroundImg(pub Handle<Image>);
+pub struct BackgroundLayers {
+    pub background: Handle<ParallaxBackground>,
+    /// Seconds the new background takes to fade in.
+    pub fade: f32,
+}
+
+/// A single parallax layer.
+#[derive(Asset, TypePath, Debug)]
+pub struct BackgroundLayer {
+    pub name: String,
+    #[dependency]
+    pub texture: Handle<Image>,
+    /// How far back the layer is drawn, bigger is farther.
+    pub depth: f32,
+    pub speed: f32,
+    pub offset_y: f32,
+    /// Size of one tile, or the size of the arena.
+    pub size: Option<Vec2>,
+    /// Tint of the texture.
+    pub color: Color,
+}
+
+/// All the layers defined in a `.layers.ron` file.
+#[derive(Asset, TypePath, Debug)]
+pub struct ParallaxBackground {
+    #[dependency]
+    pub layers: Vec<Handle<BackgroundLayer>>,
+}
+
+/// The contents of a `.layers.ron` file.
+#[derive(Deserialize)]
+pub struct LayersFile {
+    layers: Vec<BackgroundLayerDef>,
+}
+
+#[derive(Deserialize)]
+struct BackgroundLayerDef {
+    name: String,
+    texture: String,
+    depth: f32,
+    speed: f32,
+    #[serde(default)]
+    offset_y: f32,
+    #[serde(default)]
+    size: Option<[f32; 2]>,
+    #[serde(default)]
+    color: Option<[f32; 3]>,
+}
+
+impl RonAsset for ParallaxBackground {
+    type File = LayersFile;
+
+    fn from_file(file: LayersFile, load_context: &mut LoadContext) -> Self {
+        let layers = file
+            .layers
+            .into_iter()
+            .map(|def| {
+                let layer = BackgroundLayer {
+                    texture: load_context.load(def.texture),
+                    depth: def.depth,
+                    speed: def.speed,
+                    offset_y: def.offset_y,
+                    size: def.size.map(Vec2::from_array),
+                    color: def
+                        .color
+                        .map_or(Color::WHITE, |[r, g, b]| Color::rgb(r, g, b)),
+                    name: def.name,
+                };
+                load_context.add_labeled_asset(layer.name.clone(), layer)
+            })
+            .collect();
+
+        ParallaxBackground { layers }
+    }
+}
 
 pub struct BackgroundPlugin;
 
 impl Plugin for BackgroundPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            OnEnter(GameState::InGame),
-            (background_setup.before(setup),),
-        )
-        .add_systems(Update, move_background.run_if(in_state(GameState::InGame)));
+        app.init_asset::<BackgroundLayer>()
+            .init_asset::<ParallaxBackground>()
+            .register_asset_loader(RonAssetLoader::<ParallaxBackground>::new(&["layers.ron"]))
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (background_setup.before(setup),),
+            )
+            .add_systems(
+                Update,
+                (
+                    change_background
+                        .run_if(resource_exists::<BackgroundLayers>())
+                        .run_if(
+                            resource_changed::<BackgroundLayers>()
+                                .or_else(resource_changed::<ArenaBounds>()),
+                        ),
+                    fade_in_background,
+                    move_background.after(update_world_speed),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
     }
 }
 
-pub fn initialize_background(commands: &mut Commands, texture: Handle<Image>, velocity: Velocity) {
-    let sprite = |transform| SpriteBundle {
-        sprite: Sprite {
-            custom_size: Some(Vec2::new(constants::WIDTH, constants::HEIGHT)),
-            ..default()
-        },
-        texture: texture.clone(),
-        transform,
-        ..default()
+/// Spawns enough tiles of `layer` to cover the arena while it scrolls, fading
+/// them in over `fade` seconds if given.
+pub fn initialize_layer(
+    commands: &mut Commands,
+    layer: &BackgroundLayer,
+    background: AssetId<ParallaxBackground>,
+    bounds: &ArenaBounds,
+    fade: Option<f32>,
+) {
+    let size = layer
+        .size
+        .unwrap_or(Vec2::new(bounds.width(), bounds.height()));
+    // One more tile than fits on screen, so there's always one coming in
+    let tiles = (bounds.width() / size.x).ceil() as u32 + 1;
+    let layer_width = size.x * tiles as f32;
+    let left = bounds.min.x + size.x / 2.;
+    // Fading tiles go in front of the ones they replace
+    let (alpha, z) = match fade {
+        Some(_) => (0., -layer.depth + 0.01),
+        None => (1., -layer.depth),
     };
-    commands.spawn((sprite(Transform::IDENTITY), Background));
-    commands.spawn((
-        sprite(Transform::from_xyz(
-            constants::MAX_X + constants::HALF_WIDTH,
-            0.,
-            0.,
-        )),
-        Background,
-    ));
-    commands.spawn((velocity, Background));
+
+    for tile in 0..tiles {
+        let mut entity = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: layer.color.with_a(alpha),
+                    custom_size: Some(size),
+                    ..default()
+                },
+                texture: layer.texture.clone(),
+                transform: Transform::from_xyz(left + size.x * tile as f32, layer.offset_y, z),
+                ..default()
+            },
+            ParallaxTile {
+                speed: layer.speed,
+                layer_width,
+                background,
+            },
+            Background,
+            StateScoped(GameState::InGame),
+        ));
+        if let Some(fade) = fade {
+            entity.insert(FadeIn(Timer::from_seconds(fade, TimerMode::Once)));
+        }
+    }
 }
 
-pub fn background_setup(mut commands: Commands, images: Res<BackgroundImg>) {
-    commands.spawn(Camera2dBundle {
-        projection: OrthographicProjection {
-            scaling_mode: ScalingMode::Fixed {
-                width: constants::WIDTH,
-                height: constants::HEIGHT,
+pub fn background_setup(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle {
+            projection: OrthographicProjection {
+                // shows the same width as the game camera
+                scaling_mode: SCALING_MODE,
+                ..default()
             },
+            camera: Camera {
+                // renders after / on top of the main camera
+                order: -1,
+                ..default()
+            },
+            transform: Transform::from_xyz(0., 0., 1.0).looking_at(Vec3::ZERO, Vec3::Y),
             ..default()
         },
-        camera: Camera {
-            // renders after / on top of the main camera
-            order: -1,
-            ..default()
-        },
-        transform: Transform::from_xyz(0., 0., 1.0).looking_at(Vec3::ZERO, Vec3::Y),
-        ..default()
-    });
-    initialize_background(&mut commands, images.0.clone(), Velocity(2.0));
+        StateScoped(GameState::InGame),
+    ));
 }
 
-pub fn move_background(
-    mut query_sprites: Query<&mut Transform, (With<Background>, Without<Velocity>)>,
-    query_velocity: Query<&Velocity, With<Background>>,
-    time: Res<Time>,
+/// Spawns the layers of a new background, fading them in if there is already
+/// one on screen. The layers are rebuilt at once when the arena is resized.
+pub fn change_background(
+    mut commands: Commands,
+    current: Res<BackgroundLayers>,
+    bounds: Res<ArenaBounds>,
+    backgrounds: Res<Assets<ParallaxBackground>>,
+    layers: Res<Assets<BackgroundLayer>>,
+    tiles: Query<Entity, With<ParallaxTile>>,
 ) {
-    let (sprite1, sprite2) = query_sprites
-        .iter_mut()
-        .collect_tuple()
-        .expect("two sprites should be here");
-    let (mut transform_left, mut transform_right) = if sprite1.translation.x < sprite2.translation.x
-    {
-        (sprite1, sprite2)
-    } else {
-        (sprite2, sprite1)
+    let Some(background) = backgrounds.get(&current.background) else {
+        return;
     };
-    assert!(transform_left.translation.x < transform_right.translation.x);
-
-    let velocity = query_velocity.single();
-    let delta_time = time.delta_seconds();
-    let mut next_left_x = transform_left.translation.x - delta_time * velocity.0;
-    let next_right_x = transform_right.translation.x - delta_time * velocity.0;
+    let resized = bounds.is_changed();
+    if resized {
+        for entity in &tiles {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    let fade = (!resized && !tiles.is_empty()).then_some(current.fade);
+    for layer in background
+        .layers
+        .iter()
+        .filter_map(|handle| layers.get(handle))
+    {
+        initialize_layer(&mut commands, layer, current.background.id(), &bounds, fade);
+    }
+}
 
-    if next_left_x + constants::HALF_WIDTH <= constants::MIN_X {
-        let delta = constants::MIN_X - (next_left_x + constants::HALF_WIDTH);
-        next_left_x = constants::WIDTH + delta;
+/// Fades in the tiles of a new background, then despawns the previous ones.
+pub fn fade_in_background(
+    mut commands: Commands,
+    time: Res<Time>,
+    current: Res<BackgroundLayers>,
+    mut fading: Query<(Entity, &mut FadeIn, &mut Sprite, &mut Transform)>,
+    tiles: Query<(Entity, &ParallaxTile)>,
+) {
+    if fading.is_empty() {
+        return;
+    }
+    let mut still_fading = false;
+    for (entity, mut fade, mut sprite, mut transform) in &mut fading {
+        fade.0.tick(time.delta());
+        sprite.color.set_a(fade.0.percent());
+        if fade.0.finished() {
+            transform.translation.z -= 0.01;
+            commands.entity(entity).remove::<FadeIn>();
+        } else {
+            still_fading = true;
+        }
+    }
+    if still_fading {
+        return;
     }
+    let id = current.background.id();
+    for (entity, tile) in &tiles {
+        if tile.background != id {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn move_background(
+    mut tiles: Query<(&ParallaxTile, &mut Transform, &Sprite)>,
+    bounds: Res<ArenaBounds>,
+    world_speed: Res<WorldSpeed>,
+    time: Res<Time<Real>>,
+) {
+    // The world speed already accounts for pauses and slow motion
+    let distance = time.delta_seconds() * world_speed.current();
 
-    transform_left.translation.x = next_left_x;
-    transform_right.translation.x = next_right_x
+    for (tile, mut transform, sprite) in &mut tiles {
+        let half_width = sprite.custom_size.map_or(0., |size| size.x / 2.);
+        transform.translation.x -= distance * tile.speed;
+        // Move tiles that left the screen behind the last one
+        while transform.translation.x + half_width <= bounds.min.x {
+            transform.translation.x += tile.layer_width;
+        }
+    }
 }
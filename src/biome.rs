@@ -0,0 +1,266 @@
+use crate::*;
+use bevy::asset::LoadContext;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+/// When a biome takes over from the previous one.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum BiomeStart {
+    /// Once the world has scrolled this far since the start of the run.
+    Distance(f32),
+    /// Once the player has this many points.
+    Score(u32),
+}
+
+impl BiomeStart {
+    fn reached(&self, distance: f32, score: u32) -> bool {
+        match *self {
+            BiomeStart::Distance(start) => distance >= start,
+            BiomeStart::Score(start) => score >= start,
+        }
+    }
+}
+
+/// A theme of the run: its background, lighting and enemies.
+#[derive(Asset, TypePath, Debug)]
+pub struct Biome {
+    pub name: String,
+    pub start: BiomeStart,
+    #[dependency]
+    pub background: Handle<ParallaxBackground>,
+    pub light_color: Color,
+    pub light_intensity: f32,
+    /// Spawn weight multiplier of the enemies, by archetype name.
+    pub enemies: HashMap<String, f32>,
+    /// Seconds it takes to blend in this biome.
+    pub transition: f32,
+}
+
+impl Biome {
+    /// How much more likely an enemy is to spawn in this biome, 1 if unlisted.
+    pub fn enemy_weight(&self, archetype: &str) -> f32 {
+        self.enemies.get(archetype).copied().unwrap_or(1.)
+    }
+}
+
+/// All the biomes of a `.biomes.ron` file, in the order they appear in a run.
+#[derive(Asset, TypePath, Debug)]
+pub struct Biomes {
+    #[dependency]
+    pub biomes: Vec<Handle<Biome>>,
+}
+
+/// The contents of a `.biomes.ron` file.
+#[derive(Deserialize)]
+pub struct BiomesFile {
+    biomes: Vec<BiomeDef>,
+}
+
+#[derive(Deserialize)]
+struct BiomeDef {
+    name: String,
+    start: BiomeStart,
+    background: String,
+    light_color: [f32; 3],
+    light_intensity: f32,
+    #[serde(default)]
+    enemies: HashMap<String, f32>,
+    #[serde(default = "default_transition")]
+    transition: f32,
+}
+
+fn default_transition() -> f32 {
+    2.
+}
+
+impl RonAsset for Biomes {
+    type File = BiomesFile;
+
+    fn from_file(file: BiomesFile, load_context: &mut LoadContext) -> Self {
+        let biomes = file
+            .biomes
+            .into_iter()
+            .map(|def| {
+                let [r, g, b] = def.light_color;
+                let biome = Biome {
+                    start: def.start,
+                    background: load_context.load(def.background),
+                    light_color: Color::rgb(r, g, b),
+                    light_intensity: def.light_intensity,
+                    enemies: def.enemies,
+                    transition: def.transition,
+                    name: def.name,
+                };
+                load_context.add_labeled_asset(biome.name.clone(), biome)
+            })
+            .collect();
+
+        Biomes { biomes }
+    }
+}
+
+/// Where the current run is in its biomes.
+#[derive(Resource, Default)]
+pub struct CurrentBiome {
+    pub biome: Handle<Biome>,
+    pub index: usize,
+    /// Distance the world has scrolled since the start of the run.
+    pub distance: f32,
+    /// Light the transition started from.
+    light_from: (Color, f32),
+    transition: Timer,
+}
+
+pub struct BiomePlugin;
+
+impl Plugin for BiomePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Biome>()
+            .init_asset::<Biomes>()
+            .register_asset_loader(RonAssetLoader::<Biomes>::new(&["biomes.ron"]))
+            .init_resource::<CurrentBiome>()
+            .add_systems(OnEnter(GameState::InGame), start_biomes)
+            .add_systems(
+                Update,
+                (
+                    advance_biome
+                        .after(update_world_speed)
+                        .before(change_background),
+                    light_biome,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+fn start_biomes(
+    mut commands: Commands,
+    biomes: Res<AssetBiomes>,
+    rosters: Res<Assets<Biomes>>,
+    assets_biome: Res<Assets<Biome>>,
+    mut current: ResMut<CurrentBiome>,
+) {
+    let Some(first) = rosters
+        .get(&biomes.0)
+        .and_then(|roster| roster.biomes.first())
+    else {
+        return;
+    };
+    let Some(biome) = assets_biome.get(first) else {
+        return;
+    };
+    *current = CurrentBiome {
+        biome: first.clone(),
+        light_from: (biome.light_color, biome.light_intensity),
+        ..default()
+    };
+    commands.insert_resource(BackgroundLayers {
+        background: biome.background.clone(),
+        fade: 0.,
+    });
+}
+
+fn advance_biome(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    world_speed: Res<WorldSpeed>,
+    score: Res<Score>,
+    biomes: Res<AssetBiomes>,
+    rosters: Res<Assets<Biomes>>,
+    assets_biome: Res<Assets<Biome>>,
+    mut current: ResMut<CurrentBiome>,
+    lights: Query<&PointLight>,
+) {
+    current.distance += time.delta_seconds() * world_speed.current();
+
+    let Some(roster) = rosters.get(&biomes.0) else {
+        return;
+    };
+    let Some(next) = roster.biomes.get(current.index + 1) else {
+        return;
+    };
+    let Some(biome) = assets_biome.get(next) else {
+        return;
+    };
+    if !biome.start.reached(current.distance, score.0) {
+        return;
+    }
+
+    info!("entering biome {}", biome.name);
+    // Blend from wherever the light is, even in the middle of a transition
+    if let Ok(light) = lights.get_single() {
+        current.light_from = (light.color, light.intensity);
+    }
+    current.index += 1;
+    current.biome = next.clone();
+    current.transition = Timer::from_seconds(biome.transition, TimerMode::Once);
+    commands.insert_resource(BackgroundLayers {
+        background: biome.background.clone(),
+        fade: biome.transition,
+    });
+}
+
+fn light_biome(
+    time: Res<Time>,
+    assets_biome: Res<Assets<Biome>>,
+    mut current: ResMut<CurrentBiome>,
+    mut lights: Query<&mut PointLight>,
+) {
+    let Some(biome) = assets_biome.get(&current.biome) else {
+        return;
+    };
+    current.transition.tick(time.delta());
+    let t = current.transition.percent();
+    let (from_color, from_intensity) = current.light_from;
+
+    for mut light in &mut lights {
+        light.color = Vec4::from(from_color)
+            .lerp(Vec4::from(biome.light_color), t)
+            .into();
+        light.intensity = from_intensity + (biome.light_intensity - from_intensity) * t;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlisted_enemies_keep_their_weight() {
+        let biome = Biome {
+            name: "dusk".to_string(),
+            start: BiomeStart::Distance(0.),
+            background: Handle::default(),
+            light_color: Color::WHITE,
+            light_intensity: 1.,
+            enemies: HashMap::from_iter([("frijol_rojo".to_string(), 2.5)]),
+            transition: 2.,
+        };
+        assert_eq!(biome.enemy_weight("frijol_rojo"), 2.5);
+        assert_eq!(biome.enemy_weight("frijol_amarillo"), 1.);
+    }
+
+    #[test]
+    fn biomes_start_at_a_distance_or_a_score() {
+        assert!(!BiomeStart::Distance(100.).reached(99., 5000));
+        assert!(BiomeStart::Distance(100.).reached(100., 0));
+        assert!(!BiomeStart::Score(1000).reached(5000., 999));
+        assert!(BiomeStart::Score(1000).reached(0., 1000));
+    }
+
+    #[test]
+    fn shipped_biomes_parse() {
+        let file: BiomesFile = ron::from_str(include_str!("../assets/run.biomes.ron")).unwrap();
+        let names: Vec<&str> = file.biomes.iter().map(|def| def.name.as_str()).collect();
+        assert_eq!(names, ["day", "dusk", "night"]);
+
+        let day = &file.biomes[0];
+        assert!(day.enemies.is_empty());
+        assert_eq!(day.transition, 2.);
+        let night = &file.biomes[2];
+        assert!(matches!(night.start, BiomeStart::Score(1000)));
+        assert_eq!(night.enemies.get("frijol_amarillo"), Some(&0.5));
+        assert_eq!(night.transition, 4.);
+    }
+}
@@ -1,7 +1,3 @@
+/// Size of the part of the world always in view, on any window.
 pub const WIDTH: f32 = 16.;
 pub const HEIGHT: f32 = 9.;
-pub const HALF_WIDTH: f32 = 8.0;
-pub const HALF_HEIGHT: f32 = 4.5;
-pub const MAX_X: f32 = HALF_WIDTH;
-pub const MIN_X: f32 = -MAX_X;
-pub const MIN_Y: f32 = -HALF_HEIGHT;
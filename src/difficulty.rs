@@ -0,0 +1,250 @@
+use crate::GameState;
+use bevy::prelude::*;
+
+/// Tuning of how a run gets harder over time.
+///
+/// A run is made of waves separated by short pauses without spawns. Every wave
+/// is one level harder than the previous one.
+#[derive(Resource, Clone, Debug)]
+pub struct DifficultyCurve {
+    /// Seconds of spawning in a single wave.
+    pub wave_duration: f32,
+    /// Seconds without spawns between two waves.
+    pub pause_duration: f32,
+    /// Seconds between spawns on the first wave.
+    pub base_spawn_interval: f32,
+    /// The spawn interval never gets shorter than this.
+    pub min_spawn_interval: f32,
+    /// Factor applied to the spawn interval on every level.
+    pub spawn_interval_decay: f32,
+    /// Enemy speed added on every level, relative to the base speed.
+    pub speed_per_level: f32,
+    pub max_speed_multiplier: f32,
+    /// Number of levels needed to spawn one more enemy at once.
+    pub levels_per_extra_spawn: u32,
+    pub max_simultaneous_spawns: u32,
+}
+
+impl Default for DifficultyCurve {
+    fn default() -> Self {
+        Self {
+            wave_duration: 20.,
+            pause_duration: 3.,
+            base_spawn_interval: 1.,
+            min_spawn_interval: 0.3,
+            spawn_interval_decay: 0.85,
+            speed_per_level: 0.15,
+            max_speed_multiplier: 3.,
+            levels_per_extra_spawn: 3,
+            max_simultaneous_spawns: 4,
+        }
+    }
+}
+
+impl DifficultyCurve {
+    pub fn spawn_interval(&self, level: u32) -> f32 {
+        (self.base_spawn_interval * self.spawn_interval_decay.powi(level as i32))
+            .max(self.min_spawn_interval)
+    }
+
+    pub fn speed_multiplier(&self, level: u32) -> f32 {
+        (1. + self.speed_per_level * level as f32).min(self.max_speed_multiplier)
+    }
+
+    pub fn simultaneous_spawns(&self, level: u32) -> u32 {
+        (1 + level / self.levels_per_extra_spawn.max(1)).min(self.max_simultaneous_spawns)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavePhase {
+    Wave,
+    Pause,
+}
+
+/// The difficulty of the current run.
+#[derive(Resource, Debug)]
+pub struct Difficulty {
+    /// Starts at zero and goes up by one on every wave.
+    pub level: u32,
+    /// Seconds since the run started.
+    pub elapsed: f32,
+    pub phase: WavePhase,
+    phase_timer: Timer,
+    spawn_timer: Timer,
+}
+
+impl Difficulty {
+    pub fn new(curve: &DifficultyCurve) -> Self {
+        Self {
+            level: 0,
+            elapsed: 0.,
+            phase: WavePhase::Wave,
+            phase_timer: Timer::from_seconds(curve.wave_duration, TimerMode::Once),
+            spawn_timer: Timer::from_seconds(curve.spawn_interval(0), TimerMode::Repeating),
+        }
+    }
+
+    /// Whether enemies should be spawned this frame.
+    pub fn should_spawn(&self) -> bool {
+        self.phase == WavePhase::Wave && self.spawn_timer.just_finished()
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::new(&DifficultyCurve::default())
+    }
+}
+
+/// How fast the world moves past the player.
+///
+/// Everything that scrolls with the world should move at [`WorldSpeed::current`]
+/// per real second, so it speeds up with the difficulty and slows down or
+/// freezes with the game.
+#[derive(Resource, Debug)]
+pub struct WorldSpeed {
+    /// Units per second at the start of a run.
+    pub base: f32,
+    /// Set by the difficulty level.
+    pub multiplier: f32,
+    /// Relative speed of the game time, zero while paused.
+    pub time_scale: f32,
+}
+
+impl WorldSpeed {
+    pub fn current(&self) -> f32 {
+        self.base * self.multiplier * self.time_scale
+    }
+}
+
+impl Default for WorldSpeed {
+    fn default() -> Self {
+        Self {
+            base: 2.,
+            multiplier: 1.,
+            time_scale: 1.,
+        }
+    }
+}
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DifficultyCurve>()
+            .init_resource::<Difficulty>()
+            .init_resource::<WorldSpeed>()
+            .add_systems(OnEnter(GameState::InGame), reset_difficulty)
+            .add_systems(
+                Update,
+                (advance_difficulty, update_world_speed)
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+fn reset_difficulty(
+    mut difficulty: ResMut<Difficulty>,
+    mut world_speed: ResMut<WorldSpeed>,
+    curve: Res<DifficultyCurve>,
+) {
+    *difficulty = Difficulty::new(&curve);
+    world_speed.multiplier = curve.speed_multiplier(0);
+}
+
+pub fn advance_difficulty(
+    time: Res<Time>,
+    curve: Res<DifficultyCurve>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    let delta = time.delta();
+    difficulty.elapsed += delta.as_secs_f32();
+    difficulty.spawn_timer.tick(delta);
+
+    if !difficulty.phase_timer.tick(delta).just_finished() {
+        return;
+    }
+    match difficulty.phase {
+        WavePhase::Wave => {
+            difficulty.phase = WavePhase::Pause;
+            difficulty.phase_timer = Timer::from_seconds(curve.pause_duration, TimerMode::Once);
+        }
+        WavePhase::Pause => {
+            let level = difficulty.level + 1;
+            difficulty.level = level;
+            difficulty.phase = WavePhase::Wave;
+            difficulty.phase_timer = Timer::from_seconds(curve.wave_duration, TimerMode::Once);
+            difficulty.spawn_timer =
+                Timer::from_seconds(curve.spawn_interval(level), TimerMode::Repeating);
+        }
+    }
+}
+
+/// Follows the difficulty level and the game time, which is slowed down by
+/// power-ups and stopped by the pause menu.
+pub fn update_world_speed(
+    time: Res<Time<Virtual>>,
+    curve: Res<DifficultyCurve>,
+    difficulty: Res<Difficulty>,
+    mut world_speed: ResMut<WorldSpeed>,
+) {
+    let multiplier = curve.speed_multiplier(difficulty.level);
+    let time_scale = if time.is_paused() {
+        0.
+    } else {
+        time.relative_speed()
+    };
+    if world_speed.multiplier != multiplier || world_speed.time_scale != time_scale {
+        world_speed.multiplier = multiplier;
+        world_speed.time_scale = time_scale;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close;
+
+    #[test]
+    fn spawn_interval_decays_down_to_the_minimum() {
+        let curve = DifficultyCurve::default();
+        assert_close(curve.spawn_interval(0), 1.);
+        assert_close(curve.spawn_interval(1), 0.85);
+        assert_close(curve.spawn_interval(2), 0.7225);
+        assert_close(curve.spawn_interval(7), 0.85f32.powi(7));
+        assert_close(curve.spawn_interval(8), 0.3);
+        assert_close(curve.spawn_interval(50), 0.3);
+    }
+
+    #[test]
+    fn speed_multiplier_grows_up_to_the_maximum() {
+        let curve = DifficultyCurve::default();
+        assert_close(curve.speed_multiplier(0), 1.);
+        assert_close(curve.speed_multiplier(1), 1.15);
+        assert_close(curve.speed_multiplier(10), 2.5);
+        assert_close(curve.speed_multiplier(14), 3.);
+        assert_close(curve.speed_multiplier(50), 3.);
+    }
+
+    #[test]
+    fn one_more_spawn_every_few_levels() {
+        let curve = DifficultyCurve::default();
+        let spawns: Vec<u32> = (0..12)
+            .map(|level| curve.simultaneous_spawns(level))
+            .collect();
+        assert_eq!(spawns, [1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4]);
+        assert_eq!(curve.simultaneous_spawns(100), 4);
+    }
+
+    #[test]
+    fn zero_levels_per_extra_spawn_does_not_panic() {
+        let curve = DifficultyCurve {
+            levels_per_extra_spawn: 0,
+            ..default()
+        };
+        assert_eq!(curve.simultaneous_spawns(0), 1);
+        assert_eq!(curve.simultaneous_spawns(2), 3);
+    }
+}
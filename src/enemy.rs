@@ -0,0 +1,613 @@
+use crate::*;
+use bevy::asset::LoadContext;
+use bevy::ecs::system::EntityCommands;
+use rand::distributions::{Distribution, Uniform, WeightedIndex};
+use rand::Rng;
+use serde::Deserialize;
+
+#[derive(Component, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Enemy {
+    FrijolRojo,
+    FrijolAmarillo,
+    /// An enemy defined entirely by its [`EnemyArchetype`].
+    #[default]
+    Other,
+}
+
+/// Points awarded when the player dodges an enemy.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ScoreReward(pub u32);
+
+/// Seconds left before an enemy is despawned even if it is still in the arena.
+#[derive(Component)]
+pub struct Lifetime(pub Timer);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscapeReason {
+    /// The enemy left the arena.
+    OutOfBounds,
+    /// The enemy's [`Lifetime`] ran out.
+    Expired,
+}
+
+/// Sent when an enemy goes away without hitting the player, as it is despawned.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct EnemyEscaped {
+    pub reason: EscapeReason,
+    pub position: Vec3,
+    /// The enemy's [`ScoreReward`].
+    pub reward: u32,
+    /// Where the enemy came close to the player, if it did.
+    pub near_miss: Option<Vec3>,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct EnemyRules {
+    /// How far past the sides or the bottom of the arena an enemy can go
+    /// before it escapes.
+    pub escape_margin: f32,
+}
+
+impl Default for EnemyRules {
+    fn default() -> Self {
+        Self { escape_margin: 1. }
+    }
+}
+
+/// An enemy that was stomped on and is flattened before being despawned.
+#[derive(Component)]
+pub struct Squashed(Timer);
+
+impl Default for Squashed {
+    fn default() -> Self {
+        Self(Timer::from_seconds(0.25, TimerMode::Once))
+    }
+}
+
+/// Steers an enemy horizontally towards the player.
+#[derive(Component, Deserialize, Clone, Copy, Debug)]
+pub struct Homing {
+    acceleration: Scalar,
+    max_speed: Scalar,
+}
+
+/// The shape of an enemy collider, as written in an enemies file.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum ColliderShape {
+    Ball { radius: Scalar },
+    Capsule { height: Scalar, radius: Scalar },
+    Cuboid { x: Scalar, y: Scalar, z: Scalar },
+}
+
+impl ColliderShape {
+    pub fn collider(&self) -> Collider {
+        match *self {
+            ColliderShape::Ball { radius } => Collider::ball(radius),
+            ColliderShape::Capsule { height, radius } => Collider::capsule(height, radius),
+            ColliderShape::Cuboid { x, y, z } => Collider::cuboid(x, y, z),
+        }
+    }
+}
+
+/// Where an enemy comes into the arena from.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SpawnEdge {
+    /// Falls in from above, anywhere across the arena.
+    #[default]
+    Top,
+    /// Comes in from the right, this high above the bottom of the arena.
+    Side { height: Scalar },
+}
+
+/// Everything needed to spawn one kind of enemy.
+#[derive(Asset, TypePath, Debug)]
+pub struct EnemyArchetype {
+    pub name: String,
+    pub kind: Enemy,
+    #[dependency]
+    pub model: Handle<Scene>,
+    pub collider: ColliderShape,
+    pub velocity: Vector,
+    pub gravity_scale: Scalar,
+    pub spawn: SpawnEdge,
+    /// How far past its [`SpawnEdge`] the enemy spawns. Side enemies further
+    /// out than the escape margin are despawned right away.
+    pub spawn_offset: Scalar,
+    pub score: u32,
+    /// Relative chance of this enemy being picked by the spawner.
+    pub weight: u32,
+    /// Difficulty level from which this enemy starts spawning.
+    pub min_level: u32,
+    /// Weight added on every level after `min_level`.
+    pub weight_per_level: u32,
+    pub homing: Option<Homing>,
+    /// Seconds before the enemy is despawned, if it can stay around forever.
+    pub lifetime: Option<f32>,
+}
+
+impl EnemyArchetype {
+    /// Where to spawn the enemy, `x` picking the place along the top edge
+    /// between zero and one.
+    pub fn spawn_position(&self, bounds: &ArenaBounds, x: f32) -> Vec3 {
+        match self.spawn {
+            SpawnEdge::Top => Vec3::new(
+                bounds.min.x + bounds.width() * x,
+                bounds.max.y + self.spawn_offset,
+                0.,
+            ),
+            SpawnEdge::Side { height } => {
+                Vec3::new(bounds.max.x + self.spawn_offset, bounds.min.y + height, 0.)
+            }
+        }
+    }
+
+    /// The spawn weight of this enemy at the given difficulty level.
+    pub fn weight_at(&self, level: u32) -> u32 {
+        if level < self.min_level {
+            return 0;
+        }
+        self.weight + self.weight_per_level * (level - self.min_level)
+    }
+}
+
+/// All the archetypes defined in an `.enemies.ron` file.
+#[derive(Asset, TypePath, Debug)]
+pub struct EnemyArchetypes {
+    #[dependency]
+    pub archetypes: Vec<Handle<EnemyArchetype>>,
+}
+
+/// The contents of an `.enemies.ron` file.
+#[derive(Deserialize)]
+pub struct EnemiesFile {
+    archetypes: Vec<EnemyArchetypeDef>,
+}
+
+#[derive(Deserialize)]
+struct EnemyArchetypeDef {
+    name: String,
+    #[serde(default)]
+    kind: Enemy,
+    model: String,
+    collider: ColliderShape,
+    velocity: [Scalar; 3],
+    #[serde(default = "default_gravity_scale")]
+    gravity_scale: Scalar,
+    #[serde(default)]
+    spawn: SpawnEdge,
+    spawn_offset: Scalar,
+    score: u32,
+    weight: u32,
+    #[serde(default)]
+    min_level: u32,
+    #[serde(default)]
+    weight_per_level: u32,
+    #[serde(default)]
+    homing: Option<Homing>,
+    #[serde(default)]
+    lifetime: Option<f32>,
+}
+
+fn default_gravity_scale() -> Scalar {
+    1.
+}
+
+impl RonAsset for EnemyArchetypes {
+    type File = EnemiesFile;
+
+    fn from_file(file: EnemiesFile, load_context: &mut LoadContext) -> Self {
+        let archetypes = file
+            .archetypes
+            .into_iter()
+            .map(|def| {
+                let archetype = EnemyArchetype {
+                    kind: def.kind,
+                    model: load_context.load(def.model),
+                    collider: def.collider,
+                    velocity: Vector::from_array(def.velocity),
+                    gravity_scale: def.gravity_scale,
+                    spawn: def.spawn,
+                    spawn_offset: def.spawn_offset,
+                    score: def.score,
+                    weight: def.weight,
+                    min_level: def.min_level,
+                    weight_per_level: def.weight_per_level,
+                    homing: def.homing,
+                    lifetime: def.lifetime,
+                    name: def.name,
+                };
+                load_context.add_labeled_asset(archetype.name.clone(), archetype)
+            })
+            .collect();
+
+        EnemyArchetypes { archetypes }
+    }
+}
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EnemyArchetype>()
+            .init_asset::<EnemyArchetypes>()
+            .register_asset_loader(RonAssetLoader::<EnemyArchetypes>::new(&["enemies.ron"]))
+            .add_event::<EnemyEscaped>()
+            .init_resource::<EnemyRules>()
+            .add_systems(
+                Update,
+                (
+                    spawn_random_enemy
+                        .run_if(in_state(GameState::InGame))
+                        .after(update_world_speed),
+                    home_towards_player.run_if(in_state(GameState::InGame)),
+                    squash_enemies.run_if(in_state(GameState::InGame)),
+                    despawn_escaped_enemies.run_if(in_state(GameState::InGame)),
+                ),
+            );
+    }
+}
+
+fn spawn_random_enemy(
+    mut commands: Commands,
+    enemies: Res<AssetEnemies>,
+    rosters: Res<Assets<EnemyArchetypes>>,
+    archetypes: Res<Assets<EnemyArchetype>>,
+    difficulty: Res<Difficulty>,
+    curve: Res<DifficultyCurve>,
+    world_speed: Res<WorldSpeed>,
+    current_biome: Res<CurrentBiome>,
+    biomes: Res<Assets<Biome>>,
+    bounds: Res<ArenaBounds>,
+) {
+    if !difficulty.should_spawn() {
+        return;
+    }
+    let Some(roster) = rosters.get(&enemies.0) else {
+        return;
+    };
+    let candidates: Vec<&EnemyArchetype> = roster
+        .archetypes
+        .iter()
+        .filter_map(|handle| archetypes.get(handle))
+        .collect();
+    let level = difficulty.level;
+    let biome = biomes.get(&current_biome.biome);
+    let Ok(weights) = WeightedIndex::new(candidates.iter().map(|archetype| {
+        let biome_weight = biome.map_or(1., |biome| biome.enemy_weight(&archetype.name));
+        archetype.weight_at(level) as f32 * biome_weight
+    })) else {
+        return;
+    };
+
+    let mut rng = rand::thread_rng();
+    let dist = Uniform::new(0., 1.);
+    let speed = world_speed.multiplier;
+    for _ in 0..curve.simultaneous_spawns(level) {
+        let archetype = candidates[weights.sample(&mut rng)];
+        let x: f32 = rng.sample(dist);
+        let pos = Transform::from_translation(archetype.spawn_position(&bounds, x));
+        spawn_enemy(&mut commands, archetype, pos, speed);
+    }
+}
+
+/// Spawns an enemy, with its velocity scaled by `speed`.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    archetype: &EnemyArchetype,
+    transform: Transform,
+    speed: Scalar,
+) {
+    let mut enemy = commands.spawn((
+        RigidBody::Dynamic,
+        archetype.collider.collider(),
+        CollisionLayers::new([Layer::Enemy], [Layer::Player]),
+        LinearVelocity(archetype.velocity * speed),
+        GravityScale(archetype.gravity_scale),
+        archetype.kind,
+        ScoreReward(archetype.score),
+        SceneBundle {
+            scene: archetype.model.clone(),
+            transform,
+            ..default()
+        },
+        StateScoped(GameState::InGame),
+    ));
+    if let Some(homing) = archetype.homing {
+        enemy.insert(Homing {
+            acceleration: homing.acceleration * speed,
+            max_speed: homing.max_speed * speed,
+        });
+    }
+    if let Some(lifetime) = archetype.lifetime {
+        enemy.insert(Lifetime(Timer::from_seconds(lifetime, TimerMode::Once)));
+    }
+}
+
+fn home_towards_player(
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut enemies: Query<(&Transform, &Homing, &mut LinearVelocity), With<Enemy>>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let delta_time = time.delta_seconds();
+
+    for (transform, homing, mut linear_velocity) in &mut enemies {
+        let direction = (player.translation.x - transform.translation.x).signum();
+        linear_velocity.x = (linear_velocity.x + direction * homing.acceleration * delta_time)
+            .clamp(-homing.max_speed, homing.max_speed);
+    }
+}
+
+/// Turns a stomped enemy into a harmless body that is flattened for a moment
+/// before being despawned. It no longer counts as an [`Enemy`].
+pub fn squash_enemy(enemy: &mut EntityCommands) {
+    enemy
+        .remove::<(Enemy, Collider, Homing, Lifetime)>()
+        .insert((
+            RigidBody::Kinematic,
+            LinearVelocity::ZERO,
+            Squashed::default(),
+        ));
+}
+
+fn squash_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Squashed, &mut Transform)>,
+) {
+    for (entity, mut squashed, mut transform) in &mut query {
+        if squashed.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = squashed.0.percent();
+        transform.scale = Vec3::new(
+            1. + 0.5 * progress,
+            1. - 0.8 * progress,
+            1. + 0.5 * progress,
+        );
+    }
+}
+
+/// Despawns the enemies that left the arena or outlived their [`Lifetime`].
+fn despawn_escaped_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<EnemyRules>,
+    bounds: Res<ArenaBounds>,
+    mut escaped_events: EventWriter<EnemyEscaped>,
+    mut enemies: Query<
+        (
+            Entity,
+            &ScoreReward,
+            &Transform,
+            Option<&NearMissed>,
+            Option<&mut Lifetime>,
+        ),
+        With<Enemy>,
+    >,
+) {
+    for (entity, reward, transform, near_miss, lifetime) in &mut enemies {
+        let expired = lifetime.is_some_and(|mut lifetime| lifetime.0.tick(time.delta()).finished());
+        let reason = if bounds.has_left(transform.translation.truncate(), rules.escape_margin) {
+            EscapeReason::OutOfBounds
+        } else if expired {
+            EscapeReason::Expired
+        } else {
+            continue;
+        };
+        escaped_events.send(EnemyEscaped {
+            reason,
+            position: transform.translation,
+            reward: reward.0,
+            near_miss: near_miss.map(|near_miss| near_miss.0),
+        });
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use bevy::ecs::system::CommandQueue;
+
+    fn app() -> App {
+        let mut app = headless_app();
+        app.add_event::<EnemyEscaped>()
+            .init_resource::<EnemyRules>()
+            .init_resource::<ArenaBounds>()
+            .add_systems(Update, despawn_escaped_enemies);
+        app
+    }
+
+    fn spawn(app: &mut App, x: f32, y: f32) -> Entity {
+        app.world
+            .spawn((Enemy::Other, ScoreReward(5), Transform::from_xyz(x, y, 0.)))
+            .id()
+    }
+
+    fn archetype() -> EnemyArchetype {
+        EnemyArchetype {
+            name: "bean".to_string(),
+            kind: Enemy::Other,
+            model: Handle::default(),
+            collider: ColliderShape::Ball { radius: 1. },
+            velocity: Vector::ZERO,
+            gravity_scale: 1.,
+            spawn: SpawnEdge::Top,
+            spawn_offset: 0.,
+            score: 1,
+            weight: 1,
+            min_level: 0,
+            weight_per_level: 0,
+            homing: None,
+            lifetime: None,
+        }
+    }
+
+    #[test]
+    fn shipped_enemies_parse() {
+        let file: EnemiesFile = ron::from_str(include_str!("../assets/beans.enemies.ron")).unwrap();
+        let names: Vec<&str> = file
+            .archetypes
+            .iter()
+            .map(|def| def.name.as_str())
+            .collect();
+        assert_eq!(names, ["frijol_amarillo", "frijol_rojo", "frijol_volador"]);
+
+        let amarillo = &file.archetypes[0];
+        assert_eq!(amarillo.kind, Enemy::FrijolAmarillo);
+        assert_eq!(amarillo.gravity_scale, 1.);
+        assert_eq!(amarillo.min_level, 0);
+        assert!(amarillo.homing.is_none());
+        assert!(amarillo.lifetime.is_none());
+
+        let rojo = &file.archetypes[1];
+        assert_eq!(rojo.kind, Enemy::FrijolRojo);
+        assert_eq!(rojo.gravity_scale, 0.4);
+        assert!(rojo.homing.is_some());
+        assert_eq!(rojo.lifetime, Some(12.));
+    }
+
+    #[test]
+    fn enemies_spawn_past_their_edge() {
+        let bounds = ArenaBounds::default();
+        let mut archetype = archetype();
+        archetype.spawn_offset = 0.5;
+        assert_eq!(
+            archetype.spawn_position(&bounds, 0.25),
+            Vec3::new(bounds.min.x + bounds.width() / 4., bounds.max.y + 0.5, 0.)
+        );
+
+        archetype.spawn = SpawnEdge::Side { height: 1.4 };
+        let position = archetype.spawn_position(&bounds, 0.25);
+        assert_eq!(position.x, bounds.max.x + 0.5);
+        assert_close(position.y, bounds.min.y + 1.4);
+        // Side enemies come in before they count as escaped
+        let margin = EnemyRules::default().escape_margin;
+        assert!(!bounds.has_left(position.truncate(), margin));
+    }
+
+    #[test]
+    fn optional_enemy_fields_have_defaults() {
+        let file: EnemiesFile = ron::from_str(
+            r#"(archetypes: [(name: "bean", model: "bean.glb", collider: Ball(radius: 1.0),
+                velocity: (0.0, -1.0, 0.0), spawn_offset: 0.0, score: 1, weight: 1)])"#,
+        )
+        .unwrap();
+        let def = &file.archetypes[0];
+        assert_eq!(def.kind, Enemy::Other);
+        assert_eq!(def.velocity, [0., -1., 0.]);
+        assert_eq!(def.gravity_scale, 1.);
+        assert_eq!(def.min_level, 0);
+        assert_eq!(def.weight_per_level, 0);
+        assert!(def.homing.is_none());
+        assert!(def.lifetime.is_none());
+    }
+
+    #[test]
+    fn weight_grows_from_the_min_level() {
+        let archetype = EnemyArchetype {
+            weight: 2,
+            min_level: 3,
+            weight_per_level: 5,
+            ..archetype()
+        };
+        assert_eq!(archetype.weight_at(0), 0);
+        assert_eq!(archetype.weight_at(2), 0);
+        assert_eq!(archetype.weight_at(3), 2);
+        assert_eq!(archetype.weight_at(5), 12);
+    }
+
+    #[test]
+    fn enemies_past_a_margin_escape() {
+        let mut app = app();
+        let bounds = ArenaBounds::default();
+        let margin = EnemyRules::default().escape_margin;
+        let gone = [
+            spawn(&mut app, bounds.min.x - margin - 0.1, 0.),
+            spawn(&mut app, bounds.max.x + margin + 0.1, 0.),
+            spawn(&mut app, 0., bounds.min.y - margin - 0.1),
+        ];
+        let kept = [
+            spawn(&mut app, bounds.min.x - margin + 0.1, 0.),
+            spawn(&mut app, bounds.max.x + margin - 0.1, 0.),
+            spawn(&mut app, 0., bounds.min.y - margin + 0.1),
+            // Still falling in
+            spawn(&mut app, 0., bounds.max.y + margin + 5.),
+        ];
+        app.world
+            .entity_mut(gone[0])
+            .insert(NearMissed(Vec3::new(1., 2., 0.)));
+
+        app.update();
+
+        for entity in gone {
+            assert!(app.world.get_entity(entity).is_none());
+        }
+        for entity in kept {
+            assert!(app.world.get_entity(entity).is_some());
+        }
+        let events = events::<EnemyEscaped>(&app);
+        assert_eq!(events.len(), 3);
+        assert!(events
+            .iter()
+            .all(|event| event.reason == EscapeReason::OutOfBounds));
+        assert!(events.iter().all(|event| event.reward == 5));
+        assert_eq!(
+            events
+                .iter()
+                .filter_map(|event| event.near_miss)
+                .collect::<Vec<_>>(),
+            [Vec3::new(1., 2., 0.)]
+        );
+    }
+
+    #[test]
+    fn enemies_expire_at_the_end_of_their_lifetime() {
+        let mut app = app();
+        let enemy = spawn(&mut app, 0., 0.);
+        app.world
+            .entity_mut(enemy)
+            .insert(Lifetime(Timer::from_seconds(0.25, TimerMode::Once)));
+
+        // The first update has no delta, then time goes by 100ms per update
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(app.world.get_entity(enemy).is_some());
+        assert!(events::<EnemyEscaped>(&app).is_empty());
+
+        app.update();
+        assert!(app.world.get_entity(enemy).is_none());
+        let events = events::<EnemyEscaped>(&app);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].reason, EscapeReason::Expired);
+        assert_eq!(events[0].position, Vec3::ZERO);
+    }
+
+    #[test]
+    fn squashed_enemies_do_not_escape() {
+        let mut app = app();
+        let bounds = ArenaBounds::default();
+        let enemy = spawn(&mut app, 0., 0.);
+        app.world
+            .entity_mut(enemy)
+            .insert(Lifetime(Timer::from_seconds(0.1, TimerMode::Once)));
+
+        let mut queue = CommandQueue::default();
+        squash_enemy(&mut Commands::new(&mut queue, &app.world).entity(enemy));
+        queue.apply(&mut app.world);
+        // Falling out of the arena after the squash
+        app.world.get_mut::<Transform>(enemy).unwrap().translation.y = bounds.min.y - 10.;
+        for _ in 0..3 {
+            app.update();
+        }
+
+        assert!(app.world.get_entity(enemy).is_some());
+        assert!(events::<EnemyEscaped>(&app).is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+//! Which gamepad controls the player, and how its sticks are filtered.
+use bevy::prelude::*;
+use bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const STORAGE_KEY: &str = "gamepad";
+
+/// How a filtered stick value is mapped to movement.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// Finer control near the center of the stick.
+    Quadratic,
+    Cubic,
+}
+
+impl ResponseCurve {
+    const ALL: [ResponseCurve; 3] = [
+        ResponseCurve::Linear,
+        ResponseCurve::Quadratic,
+        ResponseCurve::Cubic,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ResponseCurve::Linear => "Linear",
+            ResponseCurve::Quadratic => "Quadratic",
+            ResponseCurve::Cubic => "Cubic",
+        }
+    }
+
+    fn apply(&self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Quadratic => value.powi(2),
+            ResponseCurve::Cubic => value.powi(3),
+        }
+    }
+}
+
+/// Stick tuning of one kind of gamepad.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct GamepadProfile {
+    /// Axis values closer to the center than this are ignored, so drifting
+    /// sticks don't move the player.
+    pub inner_dead_zone: f32,
+    /// Axis values past this count as fully pushed.
+    pub outer_dead_zone: f32,
+    pub response_curve: ResponseCurve,
+}
+
+impl Default for GamepadProfile {
+    fn default() -> Self {
+        Self {
+            inner_dead_zone: 0.15,
+            outer_dead_zone: 0.95,
+            response_curve: ResponseCurve::Linear,
+        }
+    }
+}
+
+impl GamepadProfile {
+    /// Applies the dead zones and the response curve to a raw axis value.
+    pub fn filter(&self, value: f32) -> f32 {
+        let range = (self.outer_dead_zone - self.inner_dead_zone).max(f32::EPSILON);
+        let magnitude = ((value.abs() - self.inner_dead_zone) / range).clamp(0., 1.);
+        self.response_curve.apply(magnitude).copysign(value)
+    }
+}
+
+/// A gamepad chosen from the settings screen. Gamepad ids change from one
+/// session to the next, so it is saved by name, and by its position among the
+/// connected gamepads with that name to tell identical ones apart.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Controller {
+    pub name: String,
+    pub index: usize,
+}
+
+impl Controller {
+    pub fn of(gamepads: &Gamepads, gamepad: Gamepad) -> Option<Self> {
+        let name = gamepads.name(gamepad)?;
+        let index = gamepads
+            .iter()
+            .filter(|other| other.id < gamepad.id && gamepads.name(*other) == Some(name))
+            .count();
+        Some(Self {
+            name: name.to_string(),
+            index,
+        })
+    }
+
+    pub fn label(&self) -> String {
+        match self.index {
+            0 => self.name.clone(),
+            index => format!("{} ({})", self.name, index + 1),
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GamepadConfig {
+    /// The gamepad controlling the player, any connected gamepad does if it
+    /// is `None`.
+    pub controller: Option<Controller>,
+    pub default_profile: GamepadProfile,
+    /// Profiles of specific gamepads, by name.
+    pub profiles: HashMap<String, GamepadProfile>,
+}
+
+impl GamepadConfig {
+    /// Whether input from `gamepad` should move the player.
+    pub fn controls(&self, gamepads: &Gamepads, gamepad: Gamepad) -> bool {
+        match &self.controller {
+            None => true,
+            Some(controller) => Controller::of(gamepads, gamepad).as_ref() == Some(controller),
+        }
+    }
+
+    pub fn profile(&self, name: Option<&str>) -> &GamepadProfile {
+        name.and_then(|name| self.profiles.get(name))
+            .unwrap_or(&self.default_profile)
+    }
+
+    pub fn save(&self) {
+        crate::storage::save(STORAGE_KEY, self);
+    }
+}
+
+pub struct GamepadConfigPlugin;
+
+impl Plugin for GamepadConfigPlugin {
+    fn build(&self, app: &mut App) {
+        let config = crate::storage::load::<GamepadConfig>(STORAGE_KEY).unwrap_or_default();
+        app.insert_resource(config);
+    }
+}
+
+/// The gamepad section of the settings screen.
+pub fn gamepad_ui(ui: &mut egui::Ui, config: &mut GamepadConfig, gamepads: &Gamepads) {
+    let mut controllers: Vec<Controller> = gamepads
+        .iter()
+        .filter_map(|gamepad| Controller::of(gamepads, gamepad))
+        .collect();
+    controllers.sort_by(|a, b| (&a.name, a.index).cmp(&(&b.name, b.index)));
+
+    egui::Grid::new("gamepad")
+        .num_columns(2)
+        .spacing((24., 8.))
+        .show(ui, |ui| {
+            ui.label("Gamepad");
+            egui::ComboBox::from_id_source("controller")
+                .selected_text(
+                    config
+                        .controller
+                        .as_ref()
+                        .map_or("Any".to_string(), Controller::label),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut config.controller, None, "Any");
+                    for controller in &controllers {
+                        ui.selectable_value(
+                            &mut config.controller,
+                            Some(controller.clone()),
+                            controller.label(),
+                        );
+                    }
+                });
+            ui.end_row();
+
+            // Tune the chosen gamepad on its own, or every gamepad at once
+            let profile = match &config.controller {
+                Some(controller) => config
+                    .profiles
+                    .entry(controller.name.clone())
+                    .or_insert(config.default_profile),
+                None => &mut config.default_profile,
+            };
+            ui.label("Inner dead zone");
+            ui.add(egui::Slider::new(&mut profile.inner_dead_zone, 0.0..=0.5));
+            ui.end_row();
+            ui.label("Outer dead zone");
+            ui.add(egui::Slider::new(&mut profile.outer_dead_zone, 0.5..=1.0));
+            ui.end_row();
+            ui.label("Response curve");
+            egui::ComboBox::from_id_source("response_curve")
+                .selected_text(profile.response_curve.name())
+                .show_ui(ui, |ui| {
+                    for curve in ResponseCurve::ALL {
+                        ui.selectable_value(&mut profile.response_curve, curve, curve.name());
+                    }
+                });
+            ui.end_row();
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close;
+    use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent, GamepadInfo};
+    use bevy::input::InputPlugin;
+
+    #[test]
+    fn filter_ignores_the_inner_dead_zone() {
+        let profile = GamepadProfile::default();
+        assert_eq!(profile.filter(0.), 0.);
+        assert_eq!(profile.filter(0.1), 0.);
+        assert_eq!(profile.filter(-0.15), 0.);
+    }
+
+    #[test]
+    fn filter_saturates_past_the_outer_dead_zone() {
+        let profile = GamepadProfile::default();
+        assert_eq!(profile.filter(0.95), 1.);
+        assert_eq!(profile.filter(1.), 1.);
+        assert_eq!(profile.filter(-0.99), -1.);
+    }
+
+    #[test]
+    fn filter_rescales_between_the_dead_zones() {
+        let profile = GamepadProfile {
+            inner_dead_zone: 0.2,
+            outer_dead_zone: 0.8,
+            response_curve: ResponseCurve::Linear,
+        };
+        assert_close(profile.filter(0.5), 0.5);
+        assert_close(profile.filter(-0.35), -0.25);
+
+        let quadratic = GamepadProfile {
+            response_curve: ResponseCurve::Quadratic,
+            ..profile
+        };
+        assert_close(quadratic.filter(0.5), 0.25);
+        assert_close(quadratic.filter(-0.5), -0.25);
+
+        let cubic = GamepadProfile {
+            response_curve: ResponseCurve::Cubic,
+            ..profile
+        };
+        assert_close(cubic.filter(-0.5), -0.125);
+    }
+
+    #[test]
+    fn overlapping_dead_zones_do_not_divide_by_zero() {
+        let profile = GamepadProfile {
+            inner_dead_zone: 0.5,
+            outer_dead_zone: 0.5,
+            response_curve: ResponseCurve::Linear,
+        };
+        assert_eq!(profile.filter(0.4), 0.);
+        assert_eq!(profile.filter(0.6), 1.);
+    }
+
+    #[test]
+    fn unknown_gamepads_use_the_default_profile() {
+        let tuned = GamepadProfile {
+            inner_dead_zone: 0.3,
+            ..default()
+        };
+        let config = GamepadConfig {
+            profiles: HashMap::from([("Pad".to_string(), tuned)]),
+            ..default()
+        };
+        assert_eq!(*config.profile(Some("Pad")), tuned);
+        assert_eq!(*config.profile(Some("Other")), config.default_profile);
+        assert_eq!(*config.profile(None), config.default_profile);
+    }
+
+    #[test]
+    fn identical_gamepads_are_told_apart() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, InputPlugin));
+        for (id, name) in [(0, "Pad"), (1, "Other"), (2, "Pad")] {
+            app.world.send_event(GamepadConnectionEvent {
+                gamepad: Gamepad::new(id),
+                connection: GamepadConnection::Connected(GamepadInfo {
+                    name: name.to_string(),
+                }),
+            });
+        }
+        app.update();
+        let gamepads = app.world.resource::<Gamepads>();
+
+        let first = Controller::of(gamepads, Gamepad::new(0)).unwrap();
+        let other = Controller::of(gamepads, Gamepad::new(1)).unwrap();
+        let second = Controller::of(gamepads, Gamepad::new(2)).unwrap();
+        assert_eq!((first.name.as_str(), first.index), ("Pad", 0));
+        assert_eq!((other.name.as_str(), other.index), ("Other", 0));
+        assert_eq!((second.name.as_str(), second.index), ("Pad", 1));
+        assert_eq!(second.label(), "Pad (2)");
+
+        let config = GamepadConfig {
+            controller: Some(second),
+            ..default()
+        };
+        assert!(!config.controls(gamepads, Gamepad::new(0)));
+        assert!(!config.controls(gamepads, Gamepad::new(1)));
+        assert!(config.controls(gamepads, Gamepad::new(2)));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_xpbd_3d::math::*;
+
+#[derive(Resource, Clone, Debug)]
+pub struct HealthRules {
+    pub max_lives: u32,
+    /// Seconds during which the player can't be hit again after a hit.
+    pub invulnerability: f32,
+    /// Seconds between two toggles of the player visibility while invulnerable.
+    pub blink_interval: f32,
+    /// Horizontal and vertical speed given to the player when hit.
+    pub knockback: Vector2,
+    /// Seconds the death animation plays before the run ends.
+    pub death_duration: f32,
+}
+
+impl Default for HealthRules {
+    fn default() -> Self {
+        Self {
+            max_lives: 3,
+            invulnerability: 1.5,
+            blink_interval: 0.1,
+            knockback: Vector2::new(8., 6.),
+            death_duration: 2.,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+/// Sent when the player loses their last life. Runs left through the pause
+/// menu don't send it.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GameOver;
+
+/// Added to the player when they lose their last life. The run ends, sending
+/// [`GameOver`], when the timer finishes.
+#[derive(Component)]
+pub struct Dying(pub Timer);
+
+impl Dying {
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+/// Whether the last run ended with a [`GameOver`]. It is false before the
+/// first run and during a run.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct LastRunLost(pub bool);
+
+/// Keeps an entity from being hurt until the timer finishes.
+#[derive(Component)]
+pub struct Invulnerable(pub Timer);
+
+impl Invulnerable {
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GameOver>()
+            .init_resource::<HealthRules>()
+            .init_resource::<LastRunLost>()
+            .add_systems(OnEnter(GameState::InGame), forget_last_run)
+            .add_systems(
+                Update,
+                (tick_invulnerability, end_run_after_death).run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Blinks invulnerable entities and makes them vulnerable again when their time is up.
+fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<HealthRules>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut Visibility)>,
+) {
+    for (entity, mut invulnerable, mut visibility) in &mut query {
+        if invulnerable.0.tick(time.delta()).finished() {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).remove::<Invulnerable>();
+            continue;
+        }
+
+        let blinks = (invulnerable.0.elapsed_secs() / rules.blink_interval) as u32;
+        *visibility = if blinks % 2 == 1 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn forget_last_run(mut last_run_lost: ResMut<LastRunLost>) {
+    last_run_lost.0 = false;
+}
+
+/// Ends the run once the death animation has played.
+fn end_run_after_death(
+    time: Res<Time>,
+    mut query: Query<&mut Dying>,
+    mut game_over_events: EventWriter<GameOver>,
+    mut last_run_lost: ResMut<LastRunLost>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for mut dying in &mut query {
+        if dying.0.tick(time.delta()).just_finished() {
+            game_over_events.send(GameOver);
+            last_run_lost.0 = true;
+            next_state.set(GameState::Menu);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn run_is_lost_once_the_death_animation_played() {
+        let mut app = headless_app();
+        app.add_state::<GameState>()
+            .add_event::<GameOver>()
+            .init_resource::<LastRunLost>()
+            .add_systems(Update, end_run_after_death);
+        app.world.spawn(Dying::new(0.15));
+
+        // The first update has no delta
+        app.update();
+        app.update();
+        assert!(!app.world.resource::<LastRunLost>().0);
+        assert!(events::<GameOver>(&app).is_empty());
+
+        app.update();
+        assert!(app.world.resource::<LastRunLost>().0);
+        assert_eq!(events::<GameOver>(&app).len(), 1);
+        assert_eq!(
+            app.world.resource::<NextState<GameState>>().0,
+            Some(GameState::Menu)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{Difficulty, GameOver, GameState, Score};
+use bevy::prelude::*;
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// How many runs are kept in the high score table.
+pub const MAX_HIGH_SCORES: usize = 10;
+
+const STORAGE_KEY: &str = "high_scores";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    /// Length of the run, in seconds.
+    pub duration: f32,
+    pub date: NaiveDate,
+}
+
+/// The best runs, sorted from highest to lowest score.
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct HighScores {
+    /// Name recorded with the next runs.
+    pub player_name: String,
+    pub entries: Vec<HighScore>,
+    /// Position of the last run in `entries`, if it made it to the table.
+    #[serde(skip)]
+    pub last_rank: Option<usize>,
+}
+
+impl Default for HighScores {
+    fn default() -> Self {
+        Self {
+            player_name: "Player".to_string(),
+            entries: Vec::new(),
+            last_rank: None,
+        }
+    }
+}
+
+impl HighScores {
+    /// Adds a run to the table, returning its position if it is good enough to be kept.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        let rank = self
+            .entries
+            .partition_point(|other| other.score >= entry.score);
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+
+    pub fn save(&self) {
+        crate::storage::save(STORAGE_KEY, self);
+    }
+}
+
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(crate::storage::load::<HighScores>(STORAGE_KEY).unwrap_or_default())
+            .add_systems(OnEnter(GameState::InGame), forget_last_rank)
+            .add_systems(Update, record_high_score.run_if(on_event::<GameOver>()));
+    }
+}
+
+/// The last run is only highlighted in the table until the next one starts.
+fn forget_last_rank(mut high_scores: ResMut<HighScores>) {
+    high_scores.last_rank = None;
+}
+
+fn record_high_score(
+    mut high_scores: ResMut<HighScores>,
+    score: Res<Score>,
+    difficulty: Res<Difficulty>,
+) {
+    let entry = HighScore {
+        name: high_scores.player_name.clone(),
+        score: score.0,
+        duration: difficulty.elapsed,
+        date: Local::now().date_naive(),
+    };
+    high_scores.last_rank = high_scores.insert(entry);
+    if high_scores.last_rank.is_some() {
+        high_scores.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            duration: 0.,
+            date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        }
+    }
+
+    fn scores(high_scores: &HighScores) -> Vec<u32> {
+        high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.score)
+            .collect()
+    }
+
+    #[test]
+    fn insert_keeps_the_table_sorted() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry("a", 20)), Some(0));
+        assert_eq!(high_scores.insert(entry("b", 50)), Some(0));
+        assert_eq!(high_scores.insert(entry("c", 30)), Some(1));
+        assert_eq!(high_scores.insert(entry("d", 10)), Some(3));
+        assert_eq!(scores(&high_scores), [50, 30, 20, 10]);
+    }
+
+    #[test]
+    fn ties_go_after_older_runs() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry("first", 30));
+        assert_eq!(high_scores.insert(entry("second", 30)), Some(1));
+        assert_eq!(high_scores.entries[0].name, "first");
+        assert_eq!(high_scores.entries[1].name, "second");
+    }
+
+    #[test]
+    fn insert_truncates_to_the_best_runs() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=MAX_HIGH_SCORES as u32 {
+            high_scores.insert(entry("run", score * 10));
+        }
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+
+        // Too low, or tied with the last run, to make it to the table
+        assert_eq!(high_scores.insert(entry("low", 5)), None);
+        assert_eq!(high_scores.insert(entry("tie", 10)), None);
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+
+        assert_eq!(high_scores.insert(entry("best", 1000)), Some(0));
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+        assert_eq!(high_scores.entries[0].name, "best");
+        assert_eq!(high_scores.entries.last().unwrap().score, 20);
+    }
+}
@@ -1,24 +1,70 @@
+use crate::{
+    ActivePowerUps, AirJumps, Combo, Dash, Difficulty, GameState, Health, Player, PointsAwarded,
+    ScoreSource, TouchControls, WavePhase,
+};
 use bevy::prelude::*;
 use bevy_egui::{
     egui::{self, Align2, Color32, FontId, RichText},
-    EguiContexts, EguiPlugin,
+    EguiContexts, EguiPlugin, EguiSettings,
 };
 
 #[derive(Resource, Default, Clone, Copy, Debug)]
 pub struct Score(pub u32);
 
+/// How long a score popup stays on screen, in seconds.
+const POPUP_LIFETIME: f32 = 1.;
+
+/// How far a score popup rises before disappearing, in pixels.
+const POPUP_RISE: f32 = 60.;
+
+struct ScorePopup {
+    id: u32,
+    text: String,
+    position: Vec3,
+    age: f32,
+}
+
+#[derive(Resource, Default)]
+struct ScorePopups {
+    popups: Vec<ScorePopup>,
+    next_id: u32,
+}
+
 pub struct HudPlugin;
 
 impl Plugin for HudPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EguiPlugin)
             .insert_resource(Score(0))
-            .add_systems(Update, update_score_ui);
+            .init_resource::<ScorePopups>()
+            .add_systems(OnEnter(GameState::InGame), reset_score)
+            .add_systems(
+                Update,
+                (
+                    update_score_ui,
+                    update_health_ui,
+                    update_power_up_ui,
+                    update_ability_ui,
+                    (spawn_score_popups, update_score_popups).chain(),
+                )
+                    .run_if(in_state(GameState::InGame)),
+            );
     }
 }
 
-fn update_score_ui(mut contexts: EguiContexts, score: Res<Score>) {
+fn reset_score(mut score: ResMut<Score>, mut popups: ResMut<ScorePopups>) {
+    *score = Score::default();
+    popups.popups.clear();
+}
+
+fn update_score_ui(
+    mut contexts: EguiContexts,
+    score: Res<Score>,
+    combo: Res<Combo>,
+    difficulty: Res<Difficulty>,
+) {
     let Score(score) = *score;
+    let level = difficulty.level + 1;
     egui::Area::new("score")
         .anchor(Align2::LEFT_TOP, (0., 25.))
         .show(contexts.ctx_mut(), |ui| {
@@ -27,5 +73,194 @@ fn update_score_ui(mut contexts: EguiContexts, score: Res<Score>) {
                     .color(Color32::BLACK)
                     .font(FontId::proportional(72.0)),
             );
+            ui.label(
+                RichText::new(format!("Level: {level}"))
+                    .color(Color32::BLACK)
+                    .font(FontId::proportional(36.0)),
+            );
+            if combo.0 > 1. {
+                ui.label(
+                    RichText::new(format!("Combo: x{:.2}", combo.0))
+                        .color(Color32::DARK_RED)
+                        .font(FontId::proportional(36.0)),
+                );
+            }
+        });
+
+    if difficulty.phase == WavePhase::Pause {
+        egui::Area::new("wave")
+            .anchor(Align2::CENTER_TOP, (0., 25.))
+            .show(contexts.ctx_mut(), |ui| {
+                ui.label(
+                    RichText::new(format!("Level {} incoming", level + 1))
+                        .color(Color32::BLACK)
+                        .font(FontId::proportional(48.0)),
+                );
+            });
+    }
+}
+
+fn spawn_score_popups(
+    mut awarded: EventReader<PointsAwarded>,
+    mut score_popups: ResMut<ScorePopups>,
+) {
+    for event in awarded.read() {
+        let points = event.points;
+        let text = match event.source {
+            // Survival points tick every second, a popup would just be noise
+            ScoreSource::Survival => continue,
+            ScoreSource::Dodge => format!("+{points}"),
+            ScoreSource::NearMiss => format!("Close call! +{points}"),
+            ScoreSource::Stomp => format!("Stomp! +{points}"),
+        };
+        let id = score_popups.next_id;
+        score_popups.next_id = id.wrapping_add(1);
+        score_popups.popups.push(ScorePopup {
+            id,
+            text,
+            position: event.position,
+            age: 0.,
+        });
+    }
+}
+
+fn update_score_popups(
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+    egui_settings: Res<EguiSettings>,
+    mut score_popups: ResMut<ScorePopups>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    let delta_time = time.delta_seconds();
+    score_popups.popups.retain_mut(|popup| {
+        popup.age += delta_time;
+        popup.age < POPUP_LIFETIME
+    });
+
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    // egui points are logical pixels scaled by the interface scale
+    let scale = egui_settings.scale_factor as f32;
+    let ctx = contexts.ctx_mut();
+    for popup in &score_popups.popups {
+        let Some(position) = camera.world_to_viewport(camera_transform, popup.position) else {
+            continue;
+        };
+        let position = position / scale;
+        let progress = popup.age / POPUP_LIFETIME;
+        let alpha = ((1. - progress) * 255.) as u8;
+        egui::Area::new(egui::Id::new(("score_popup", popup.id)))
+            .fixed_pos((position.x, position.y - progress * POPUP_RISE))
+            .pivot(Align2::CENTER_BOTTOM)
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    RichText::new(&popup.text)
+                        .color(Color32::from_rgba_unmultiplied(120, 20, 20, alpha))
+                        .font(FontId::proportional(32.0)),
+                );
+            });
+    }
+}
+
+fn update_health_ui(
+    mut contexts: EguiContexts,
+    touch_controls: Res<TouchControls>,
+    egui_settings: Res<EguiSettings>,
+    player_query: Query<&Health, With<Player>>,
+) {
+    let Ok(health) = player_query.get_single() else {
+        return;
+    };
+    let inset = touch_controls.top_right_inset(&egui_settings);
+    egui::Area::new("health")
+        .anchor(Align2::RIGHT_TOP, (-25. - inset, 25.))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                for life in 0..health.max {
+                    let color = if life < health.current {
+                        Color32::RED
+                    } else {
+                        Color32::DARK_GRAY
+                    };
+                    ui.label(
+                        RichText::new("\u{2665}")
+                            .color(color)
+                            .font(FontId::proportional(64.0)),
+                    );
+                }
+            });
+        });
+}
+
+fn update_power_up_ui(
+    mut contexts: EguiContexts,
+    touch_controls: Res<TouchControls>,
+    egui_settings: Res<EguiSettings>,
+    active: Res<ActivePowerUps>,
+) {
+    if active.0.is_empty() {
+        return;
+    }
+    let inset = touch_controls.top_right_inset(&egui_settings);
+    egui::Area::new("power_ups")
+        .anchor(Align2::RIGHT_TOP, (-25. - inset, 110.))
+        .show(contexts.ctx_mut(), |ui| {
+            for effect in &active.0 {
+                let remaining = effect.timer.remaining_secs();
+                ui.label(
+                    RichText::new(format!("{} {remaining:.1}s", effect.power_up.name()))
+                        .color(Color32::BLACK)
+                        .font(FontId::proportional(32.0)),
+                );
+            }
+        });
+}
+
+fn update_ability_ui(
+    mut contexts: EguiContexts,
+    player_query: Query<(Option<&AirJumps>, Option<&Dash>), With<Player>>,
+) {
+    let Ok((air_jumps, dash)) = player_query.get_single() else {
+        return;
+    };
+    // Below the "Level N incoming" banner
+    egui::Area::new("abilities")
+        .anchor(Align2::CENTER_TOP, (0., 95.))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                if let Some(air_jumps) = air_jumps {
+                    for jump in 0..air_jumps.max {
+                        let color = if jump < air_jumps.remaining() {
+                            Color32::BLACK
+                        } else {
+                            Color32::DARK_GRAY
+                        };
+                        ui.label(
+                            RichText::new("\u{25B2}")
+                                .color(color)
+                                .font(FontId::proportional(32.0)),
+                        );
+                    }
+                }
+                if let Some(dash) = dash {
+                    let text = if dash.is_ready() {
+                        "Dash".to_string()
+                    } else {
+                        format!("Dash {:.1}s", dash.cooldown.remaining_secs())
+                    };
+                    let color = if dash.is_ready() {
+                        Color32::BLACK
+                    } else {
+                        Color32::DARK_GRAY
+                    };
+                    ui.label(
+                        RichText::new(text)
+                            .color(color)
+                            .font(FontId::proportional(32.0)),
+                    );
+                }
+            });
         });
 }
@@ -0,0 +1,551 @@
+//! Maps keys, mouse buttons and gamepad buttons or axes to logical input
+//! actions, so that gameplay code doesn't need to know what is bound to what.
+use crate::{AssetInputMap, GameState, GamepadConfig, RonAsset, RonAssetLoader};
+use bevy::asset::LoadContext;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32},
+    EguiContexts,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+const STORAGE_KEY: &str = "input";
+
+/// How far an axis or analog button has to be pushed to count as pressed.
+const PRESS_THRESHOLD: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputAction {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Pause,
+    Dash,
+    /// Crouches on the ground and falls faster in the air.
+    #[serde(alias = "FastFall")]
+    Down,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 6] = [
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Jump,
+        InputAction::Pause,
+        InputAction::Dash,
+        InputAction::Down,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputAction::MoveLeft => "Move left",
+            InputAction::MoveRight => "Move right",
+            InputAction::Jump => "Jump",
+            InputAction::Pause => "Pause",
+            InputAction::Dash => "Dash",
+            InputAction::Down => "Crouch / fast fall",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// Something the player can press to trigger an [`InputAction`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButtonType),
+    /// One half of a gamepad axis.
+    GamepadAxis(GamepadAxisType, AxisDirection),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+            Binding::GamepadButton(button) => format!("Pad {button:?}"),
+            Binding::GamepadAxis(axis, AxisDirection::Positive) => format!("Pad {axis:?}+"),
+            Binding::GamepadAxis(axis, AxisDirection::Negative) => format!("Pad {axis:?}-"),
+        }
+    }
+}
+
+/// The bindings of every [`InputAction`].
+#[derive(Resource, Asset, TypePath, Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct InputMap {
+    pub bindings: HashMap<InputAction, Vec<Binding>>,
+}
+
+/// The bindings the game ships with, from `default.input.ron`.
+#[derive(Resource, Default)]
+pub struct DefaultInputMap(pub InputMap);
+
+impl RonAsset for InputMap {
+    type File = Self;
+
+    fn from_file(file: Self, _load_context: &mut LoadContext) -> Self {
+        file
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: InputAction) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The other action `binding` is already used by, if any.
+    pub fn conflict(&self, action: InputAction, binding: Binding) -> Option<InputAction> {
+        InputAction::ALL
+            .into_iter()
+            .find(|&other| other != action && self.bindings(other).contains(&binding))
+    }
+
+    /// Binds `binding` to `action`, taking it away from any other action.
+    pub fn bind(&mut self, action: InputAction, binding: Binding) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|&other| other != binding);
+        }
+        self.bindings.entry(action).or_default().push(binding);
+    }
+
+    /// Gives the default bindings to actions missing from a saved map, such as
+    /// actions added after it was saved.
+    fn add_missing(&mut self, defaults: &InputMap) {
+        for (action, bindings) in &defaults.bindings {
+            self.bindings
+                .entry(*action)
+                .or_insert_with(|| bindings.clone());
+        }
+    }
+
+    pub fn unbind(&mut self, action: InputAction, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|&other| other != binding);
+        }
+    }
+
+    pub fn save(&self) {
+        crate::storage::save(STORAGE_KEY, self);
+    }
+}
+
+/// The state of every [`InputAction`] this frame.
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    values: HashMap<InputAction, f32>,
+    pressed: HashSet<InputAction>,
+    just_pressed: HashSet<InputAction>,
+    /// What was pressed on the previous frame.
+    previous: HashSet<InputAction>,
+}
+
+impl ActionState {
+    /// How far the action is pushed, between zero and one.
+    pub fn value(&self, action: InputAction) -> f32 {
+        self.values.get(&action).copied().unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: InputAction) -> bool {
+        self.previous.contains(&action) && !self.pressed.contains(&action)
+    }
+
+    /// Pushes `action` by `value`, for input that doesn't come from an
+    /// [`InputMap`] binding. The strongest input of every frame wins.
+    pub fn push(&mut self, action: InputAction, value: f32) {
+        let value = value.min(1.).max(self.value(action));
+        self.values.insert(action, value);
+        if value >= PRESS_THRESHOLD {
+            self.pressed.insert(action);
+            if !self.previous.contains(&action) {
+                self.just_pressed.insert(action);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.previous = std::mem::take(&mut self.pressed);
+        self.values.clear();
+        self.just_pressed.clear();
+    }
+}
+
+/// A binding being chosen from the settings screen.
+#[derive(Resource, Default)]
+pub struct Rebinding {
+    /// Action waiting for a key or button press, escape cancels.
+    waiting: Option<InputAction>,
+    /// A pressed binding that is already used by another action, waiting to
+    /// be confirmed.
+    conflict: Option<(InputAction, Binding, InputAction)>,
+    /// Gamepad axis values on the previous frame.
+    previous_axes: HashMap<GamepadAxis, f32>,
+}
+
+impl Rebinding {
+    pub fn cancel(&mut self) {
+        self.waiting = None;
+        self.conflict = None;
+    }
+}
+
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        let input_map = crate::storage::load::<InputMap>(STORAGE_KEY).unwrap_or_default();
+        app.insert_resource(input_map)
+            .init_asset::<InputMap>()
+            .register_asset_loader(RonAssetLoader::<InputMap>::new(&["input.ron"]))
+            .init_resource::<DefaultInputMap>()
+            .init_resource::<ActionState>()
+            .init_resource::<Rebinding>()
+            .add_systems(OnExit(GameState::AssetLoading), apply_default_bindings)
+            .add_systems(PreUpdate, update_action_state.after(InputSystem))
+            .add_systems(Update, capture_binding);
+    }
+}
+
+/// Keeps the loaded defaults around for the settings screen, and gives them to
+/// the actions the saved bindings don't cover.
+fn apply_default_bindings(
+    asset: Res<AssetInputMap>,
+    input_maps: Res<Assets<InputMap>>,
+    mut defaults: ResMut<DefaultInputMap>,
+    mut input_map: ResMut<InputMap>,
+) {
+    let Some(default_map) = input_maps.get(&asset.0) else {
+        warn!("default input map is missing, only saved bindings will work");
+        return;
+    };
+    defaults.0 = default_map.clone();
+    input_map.add_missing(&defaults.0);
+}
+
+pub fn update_action_state(
+    input_map: Res<InputMap>,
+    mut state: ResMut<ActionState>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_config: Res<GamepadConfig>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+) {
+    let controlling: Vec<Gamepad> = gamepads
+        .iter()
+        .filter(|&gamepad| gamepad_config.controls(&gamepads, gamepad))
+        .collect();
+    let value = |binding: &Binding| -> f32 {
+        match *binding {
+            Binding::Key(key) => keys.pressed(key) as u8 as f32,
+            Binding::Mouse(button) => mouse_buttons.pressed(button) as u8 as f32,
+            Binding::GamepadButton(button_type) => controlling.iter().any(|&gamepad| {
+                gamepad_buttons.pressed(GamepadButton {
+                    gamepad,
+                    button_type,
+                })
+            }) as u8 as f32,
+            Binding::GamepadAxis(axis_type, direction) => controlling
+                .iter()
+                .filter_map(|&gamepad| {
+                    let value = gamepad_axes.get(GamepadAxis { gamepad, axis_type })?;
+                    Some(gamepad_config.profile(gamepads.name(gamepad)).filter(value))
+                })
+                .map(|value| match direction {
+                    AxisDirection::Positive => value,
+                    AxisDirection::Negative => -value,
+                })
+                .fold(0., f32::max),
+        }
+    };
+
+    state.clear();
+    for action in InputAction::ALL {
+        let action_value = input_map
+            .bindings(action)
+            .iter()
+            .map(value)
+            .fold(0., f32::max);
+        state.push(action, action_value);
+    }
+}
+
+/// The binding pressed this frame, if any. Mouse buttons are left out when
+/// `mouse_buttons` is `None`. Axes only count when they cross the threshold
+/// from their value in `previous_axes`, so sticks held or triggers resting
+/// at one end aren't bound right away.
+fn pressed_binding(
+    keys: &Input<KeyCode>,
+    mouse_buttons: Option<&Input<MouseButton>>,
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+    previous_axes: &HashMap<GamepadAxis, f32>,
+) -> Option<Binding> {
+    if let Some(&key) = keys.get_just_pressed().next() {
+        return Some(Binding::Key(key));
+    }
+    if let Some(&button) = mouse_buttons.and_then(|buttons| buttons.get_just_pressed().next()) {
+        return Some(Binding::Mouse(button));
+    }
+    if let Some(button) = gamepad_buttons.get_just_pressed().next() {
+        return Some(Binding::GamepadButton(button.button_type));
+    }
+    gamepad_axes.devices().find_map(|axis| {
+        let value = gamepad_axes.get(*axis)?;
+        let previous = previous_axes.get(axis).copied().unwrap_or_default();
+        let direction = if value >= PRESS_THRESHOLD && previous < PRESS_THRESHOLD {
+            AxisDirection::Positive
+        } else if value <= -PRESS_THRESHOLD && previous > -PRESS_THRESHOLD {
+            AxisDirection::Negative
+        } else {
+            return None;
+        };
+        Some(Binding::GamepadAxis(axis.axis_type, direction))
+    })
+}
+
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut input_map: ResMut<InputMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut contexts: EguiContexts,
+) {
+    let axes: HashMap<GamepadAxis, f32> = gamepad_axes
+        .devices()
+        .filter_map(|&axis| Some((axis, gamepad_axes.get(axis)?)))
+        .collect();
+    let previous_axes = std::mem::replace(&mut rebinding.previous_axes, axes);
+    let Some(action) = rebinding.waiting else {
+        return;
+    };
+    // Clicks on the settings screen are for its buttons
+    let mouse_buttons =
+        (!contexts.ctx_mut().is_pointer_over_area()).then_some(mouse_buttons.as_ref());
+    let Some(binding) = pressed_binding(
+        &keys,
+        mouse_buttons,
+        &gamepad_buttons,
+        &gamepad_axes,
+        &previous_axes,
+    ) else {
+        return;
+    };
+    rebinding.waiting = None;
+    if binding == Binding::Key(KeyCode::Escape) {
+        return;
+    }
+    match input_map.conflict(action, binding) {
+        Some(other) => rebinding.conflict = Some((action, binding, other)),
+        None if !input_map.bindings(action).contains(&binding) => {
+            input_map.bind(action, binding);
+        }
+        None => {}
+    }
+}
+
+/// The bindings section of the settings screen.
+pub fn bindings_ui(ui: &mut egui::Ui, input_map: &mut InputMap, rebinding: &mut Rebinding) {
+    egui::Grid::new("bindings")
+        .num_columns(2)
+        .spacing((24., 8.))
+        .show(ui, |ui| {
+            for action in InputAction::ALL {
+                ui.label(action.name());
+                ui.horizontal(|ui| {
+                    for binding in input_map.bindings(action).to_vec() {
+                        let button = ui.button(binding.name()).on_hover_text("Click to remove");
+                        if button.clicked() {
+                            input_map.unbind(action, binding);
+                        }
+                    }
+                    if rebinding.waiting == Some(action) {
+                        ui.label("Press a key or button...");
+                    } else if ui.button("+").clicked() {
+                        rebinding.cancel();
+                        rebinding.waiting = Some(action);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+
+    if let Some((action, binding, other)) = rebinding.conflict {
+        ui.colored_label(
+            Color32::YELLOW,
+            format!("{} is already bound to {}.", binding.name(), other.name()),
+        );
+        ui.horizontal(|ui| {
+            if ui.button(format!("Use for {}", action.name())).clicked() {
+                input_map.bind(action, binding);
+                rebinding.cancel();
+            }
+            if ui.button("Cancel").clicked() {
+                rebinding.cancel();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn just_pressed_only_on_the_first_frame() {
+        let mut state = ActionState::default();
+        state.push(InputAction::Jump, 1.);
+        assert!(state.pressed(InputAction::Jump));
+        assert!(state.just_pressed(InputAction::Jump));
+
+        state.clear();
+        state.push(InputAction::Jump, 1.);
+        assert!(state.pressed(InputAction::Jump));
+        assert!(!state.just_pressed(InputAction::Jump));
+        assert!(!state.just_released(InputAction::Jump));
+
+        state.clear();
+        assert!(!state.pressed(InputAction::Jump));
+        assert!(state.just_released(InputAction::Jump));
+
+        state.clear();
+        assert!(!state.just_released(InputAction::Jump));
+        state.push(InputAction::Jump, 1.);
+        assert!(state.just_pressed(InputAction::Jump));
+    }
+
+    #[test]
+    fn values_under_the_threshold_are_not_pressed() {
+        let mut state = ActionState::default();
+        state.push(InputAction::MoveLeft, 0.3);
+        assert_eq!(state.value(InputAction::MoveLeft), 0.3);
+        assert!(!state.pressed(InputAction::MoveLeft));
+        assert!(!state.just_pressed(InputAction::MoveLeft));
+    }
+
+    #[test]
+    fn strongest_push_wins() {
+        let mut state = ActionState::default();
+        state.push(InputAction::MoveRight, 0.8);
+        state.push(InputAction::MoveRight, 0.2);
+        assert_eq!(state.value(InputAction::MoveRight), 0.8);
+        state.push(InputAction::MoveRight, 3.);
+        assert_eq!(state.value(InputAction::MoveRight), 1.);
+    }
+
+    #[test]
+    fn axes_are_captured_when_they_cross_the_threshold() {
+        let keys = Input::<KeyCode>::default();
+        let gamepad_buttons = Input::<GamepadButton>::default();
+        let stick = GamepadAxis::new(Gamepad::new(0), GamepadAxisType::LeftStickX);
+        let trigger = GamepadAxis::new(Gamepad::new(0), GamepadAxisType::RightZ);
+        let mut axes = Axis::<GamepadAxis>::default();
+        let pressed = |axes: &Axis<GamepadAxis>, previous: &[(GamepadAxis, f32)]| {
+            let previous = previous.iter().copied().collect();
+            pressed_binding(&keys, None, &gamepad_buttons, axes, &previous)
+        };
+
+        // A trigger resting at -1 and a stick already held
+        axes.set(trigger, -1.);
+        axes.set(stick, 0.9);
+        assert_eq!(pressed(&axes, &[(trigger, -1.), (stick, 0.9)]), None);
+
+        axes.set(stick, -0.9);
+        assert_eq!(
+            pressed(&axes, &[(trigger, -1.), (stick, 0.9)]),
+            Some(Binding::GamepadAxis(
+                GamepadAxisType::LeftStickX,
+                AxisDirection::Negative
+            ))
+        );
+
+        axes.set(stick, 0.);
+        axes.set(trigger, 1.);
+        assert_eq!(
+            pressed(&axes, &[(trigger, -1.), (stick, 0.)]),
+            Some(Binding::GamepadAxis(
+                GamepadAxisType::RightZ,
+                AxisDirection::Positive
+            ))
+        );
+    }
+
+    #[test]
+    fn bind_takes_the_binding_from_other_actions() {
+        let mut input_map = InputMap::default();
+        input_map.bind(InputAction::Jump, Binding::Key(KeyCode::Space));
+        assert_eq!(
+            input_map.conflict(InputAction::Dash, Binding::Key(KeyCode::Space)),
+            Some(InputAction::Jump)
+        );
+        assert_eq!(
+            input_map.conflict(InputAction::Jump, Binding::Key(KeyCode::Space)),
+            None
+        );
+
+        input_map.bind(InputAction::Dash, Binding::Key(KeyCode::Space));
+        assert!(input_map.bindings(InputAction::Jump).is_empty());
+        assert_eq!(
+            input_map.bindings(InputAction::Dash),
+            [Binding::Key(KeyCode::Space)]
+        );
+    }
+
+    #[test]
+    fn add_missing_keeps_saved_bindings() {
+        let mut defaults = InputMap::default();
+        defaults.bind(InputAction::Jump, Binding::Key(KeyCode::Space));
+        defaults.bind(InputAction::Down, Binding::Key(KeyCode::S));
+        let mut saved = InputMap::default();
+        saved.bind(InputAction::Jump, Binding::Key(KeyCode::W));
+
+        saved.add_missing(&defaults);
+        assert_eq!(
+            saved.bindings(InputAction::Jump),
+            [Binding::Key(KeyCode::W)]
+        );
+        assert_eq!(
+            saved.bindings(InputAction::Down),
+            [Binding::Key(KeyCode::S)]
+        );
+    }
+
+    #[test]
+    fn default_input_map_binds_every_action() {
+        let input_map: InputMap =
+            ron::from_str(include_str!("../assets/default.input.ron")).unwrap();
+        for action in InputAction::ALL {
+            assert!(!input_map.bindings(action).is_empty(), "{action:?}");
+        }
+        assert!(input_map
+            .bindings(InputAction::Jump)
+            .contains(&Binding::Key(KeyCode::Space)));
+    }
+
+    #[test]
+    fn old_fast_fall_action_is_read_as_down() {
+        let input_map: InputMap = ron::from_str("(bindings: { FastFall: [Key(S)] })").unwrap();
+        assert_eq!(
+            input_map.bindings(InputAction::Down),
+            [Binding::Key(KeyCode::S)]
+        );
+    }
+}
@@ -4,37 +4,62 @@
 // Feel free to delete this line.
 #![allow(clippy::too_many_arguments, clippy::type_complexity)]
 
+mod animation;
+mod arena;
 mod assets;
 mod audio;
 mod background;
+mod biome;
 mod constants;
+mod difficulty;
+mod enemy;
+mod gamepad;
+mod health;
+mod highscore;
 mod hud;
+mod input;
+mod menu;
+mod pause;
 mod plugin;
+mod powerup;
+mod scoped;
+mod scoring;
+mod settings;
+mod storage;
+#[cfg(test)]
+mod test_util;
+mod touch;
 
+use animation::*;
+use arena::*;
 pub use assets::*;
 use audio::*;
 use background::*;
+use biome::*;
+use difficulty::*;
+use enemy::*;
+use gamepad::*;
+use health::*;
+use highscore::*;
 use hud::*;
+use input::*;
+use menu::*;
+use pause::*;
 use plugin::*;
+use powerup::*;
+use scoped::*;
+use scoring::*;
+use settings::*;
+use touch::*;
 
 use bevy::audio::PlaybackMode;
-use bevy::core_pipeline::clear_color::ClearColorConfig;
-use bevy::{prelude::*, render::camera::ScalingMode};
+use bevy::prelude::*;
 
 use bevy_xpbd_3d::{math::*, prelude::*};
-use rand::distributions::{Distribution, Uniform};
-use rand::Rng;
 
 #[derive(Component)]
 struct Player;
 
-#[derive(Component)]
-enum Enemy {
-    FrijolRojo,
-    FrijolAmarillo,
-    Other,
-}
-
 #[derive(Resource)]
 pub struct SecondTimer(Timer);
 
@@ -58,6 +83,9 @@ enum GameState {
     AssetLoading,
     InGame,
     Menu,
+    /// Passes through for a frame so that restarting a run goes through
+    /// [`OnExit`] and [`OnEnter`] of [`GameState::InGame`].
+    Restart,
 }
 
 #[derive(PhysicsLayer)]
@@ -65,12 +93,15 @@ enum Layer {
     Player,
     Enemy,
     Ground,
+    Pickup,
 }
 
 fn main() {
     App::new()
         .insert_resource(bevy::asset::AssetMetaCheck::Never)
         .add_state::<GameState>()
+        .add_state_scoped(GameState::InGame)
+        .add_state_scoped(GameState::Menu)
         .add_plugins((
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
@@ -85,23 +116,36 @@ fn main() {
             HudPlugin,
             AssetLoaderPlugin,
             BackgroundPlugin,
+            DifficultyPlugin,
+            EnemyPlugin,
+            HealthPlugin,
+            HighScorePlugin,
+            MenuPlugin,
+            PausePlugin,
+            PowerUpPlugin,
+            ScoringPlugin,
+        ))
+        .add_plugins((
+            GamepadConfigPlugin,
+            InputMapPlugin,
+            SettingsPlugin,
+            TouchPlugin,
+            PlayerAnimationPlugin,
+            BiomePlugin,
+            ArenaPlugin,
         ))
         //        .add_plugins(EditorPlugin::default())
         .init_resource::<SecondTimer>()
-        .add_systems(OnExit(GameState::AssetLoading), (add_background, add_ost))
+        .add_systems(OnExit(GameState::AssetLoading), add_ost)
         .add_systems(
             OnEnter(GameState::InGame),
-            (setup, play_ost.after(background_setup)),
+            (setup, reset_timer, play_ost.after(background_setup)),
         )
         .add_systems(
             Update,
             (
-                setup_scene_once_loaded.run_if(in_state(GameState::InGame)),
                 countdown.run_if(in_state(GameState::InGame)),
-                spawn_random_enemy.run_if(in_state(GameState::InGame)),
-                update_score.run_if(in_state(GameState::InGame)),
                 handle_collisions.run_if(in_state(GameState::InGame)),
-                despawn_nonvisible_enemies.run_if(in_state(GameState::InGame)),
             ),
         )
         .run();
@@ -109,225 +153,171 @@ fn main() {
 
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    bounds: Res<ArenaBounds>,
     player_gltf: Res<PlayerModel>,
+    health_rules: Res<HealthRules>,
 ) {
     // Player
     commands.spawn((
         SceneBundle {
             scene: player_gltf.0.clone(),
             transform: Transform {
-                translation: Vec3::new(0., constants::MIN_Y + 1., 0.),
+                translation: Vec3::new(0., bounds.min.y + 1., 0.),
                 rotation: Quat::from_rotation_y(PI / 3.0),
                 ..default()
             },
             ..default()
         },
-        CharacterControllerBundle::new(Collider::capsule(2.0, 0.5), Vector::NEG_Y * 9.81 * 2.0)
-            .with_movement(30.0, 0.92, 12.0, (30.0 as Scalar).to_radians()),
-        CollisionLayers::new([Layer::Player], [Layer::Enemy, Layer::Ground]),
+        CharacterControllerBundle::new(
+            Collider::capsule(PLAYER_HEIGHT, PLAYER_RADIUS),
+            Vector::NEG_Y * 9.81 * 2.0,
+        )
+        .with_movement(30.0, 0.92, 12.0, (30.0 as Scalar).to_radians()),
+        (
+            VariableJumpHeight(0.5),
+            JumpBuffer::new(0.15),
+            CoyoteTime::new(0.1),
+            AirJumps::new(1),
+            Dash::new(14.0, 1.5),
+            FastFall { speed: 20.0 },
+            Crouch::capsule(PLAYER_HEIGHT, PLAYER_CROUCH_HEIGHT, PLAYER_RADIUS, 0.98),
+        ),
+        CollisionLayers::new(
+            [Layer::Player],
+            [Layer::Enemy, Layer::Ground, Layer::Pickup],
+        ),
         Player,
+        AnimationController::default(),
+        Health::new(health_rules.max_lives),
+        StateScoped(GameState::InGame),
     ));
 
-    //bottom
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Plane::from_size(constants::WIDTH))),
-            material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-            transform: Transform::from_translation(
-                Vec3::NEG_Y * constants::HALF_HEIGHT - (1.0 * Vec3::Y),
-            ),
-            visibility: Visibility::Hidden,
-            ..default()
-        },
-        RigidBody::Static,
-        Collider::cuboid(constants::WIDTH, 0.002, 8.0),
-        CollisionLayers::new([Layer::Ground], [Layer::Player]),
-    ));
-
-    let mut transform =
-        Transform::from_translation(Vec3::NEG_X * constants::HALF_WIDTH + (0.5 * Vec3::X));
-    transform.rotate_z(PI / 2.0);
-
-    //left
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Plane::from_size(constants::WIDTH))),
-            material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-            transform,
-            visibility: Visibility::Hidden,
-            ..default()
-        },
-        RigidBody::Static,
-        Collider::cuboid(constants::HEIGHT, 0.002, 8.0),
-        CollisionLayers::new([Layer::Ground], [Layer::Player]),
-    ));
-
-    let mut transform =
-        Transform::from_translation(Vec3::X * constants::HALF_WIDTH - (0.5 * Vec3::X));
-    transform.rotate_z(PI / 2.0);
-
-    //right
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Plane::from_size(constants::WIDTH))),
-            material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-            transform,
-            visibility: Visibility::Hidden,
-            ..default()
-        },
-        RigidBody::Static,
-        Collider::cuboid(constants::HEIGHT, 0.002, 8.0),
-        CollisionLayers::new([Layer::Ground], [Layer::Player]),
-    ));
+    for wall in Wall::ALL {
+        let (transform, collider) = wall.placement(&bounds);
+        commands.spawn((
+            TransformBundle::from_transform(transform),
+            wall,
+            RigidBody::Static,
+            collider,
+            CollisionLayers::new([Layer::Ground], [Layer::Player]),
+            StateScoped(GameState::InGame),
+        ));
+    }
 
     // Light
-    commands.spawn(PointLightBundle {
-        point_light: PointLight {
-            intensity: 6000.0,
-            range: 50.0,
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform::from_xyz(0.0, 0.0, 15.0),
-        ..default()
-    });
-
-    commands.spawn(Camera3dBundle {
-        projection: OrthographicProjection {
-            scaling_mode: ScalingMode::Fixed {
-                width: 16.,
-                height: 9.,
+    commands.spawn((
+        PointLightBundle {
+            point_light: PointLight {
+                intensity: 6000.0,
+                range: 50.0,
+                shadows_enabled: true,
+                ..default()
             },
-            ..default()
-        }
-        .into(),
-        camera_3d: Camera3d {
-            // don't clear the color while rendering this camera
-            clear_color: ClearColorConfig::None,
+            transform: Transform::from_xyz(0.0, 0.0, 15.0),
             ..default()
         },
-        transform: Transform::from_xyz(0.0, 3.0, 15.0).looking_at(Vec3::ZERO, Vec3::Y),
-        ..default()
-    });
+        StateScoped(GameState::InGame),
+    ));
 }
 
-//https://bevyengine.org/examples/3D%20Rendering/3d-viewport-to-world/
-fn top_screen_to_world(camera_query: Query<(&Camera, &GlobalTransform)>) -> Vec3 {
-    let (camera, camera_transform) = camera_query.single();
-    // Calculate a ray pointing from the camera into the world based on the cursor's position.
-    let Some(ray) = camera.viewport_to_world(camera_transform, Vec2::new(0., 0.)) else {
-        panic!("Could not obtain viewport_to_world");
-    };
-
-    // Calculate if and where the ray is hitting the ground plane.
-    let Some(distance) = ray.intersect_plane(Vec3::ZERO, Vec3::Z) else {
-        panic!("There should be an intersection with the z plane");
-    };
-    let point = ray.get_point(distance);
-    info!("point!!!! {}", point);
-    point
+fn reset_timer(mut second_timer: ResMut<SecondTimer>) {
+    *second_timer = SecondTimer::new();
 }
 
 fn countdown(time: Res<Time>, mut second_timer: ResMut<SecondTimer>) {
     second_timer.0.tick(time.delta());
 }
 
-fn spawn_random_enemy(
-    commands: Commands,
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<StandardMaterial>>,
-    enemy_scene: Res<EnemyModel>,
-    second_timer: Res<SecondTimer>,
-) {
-    if second_timer.0.just_finished() {
-        let dist = Uniform::new(constants::MIN_X, constants::MAX_X);
-        let x: f32 = rand::thread_rng().sample(dist);
-        let pos = Transform::from_xyz(x, 5., 0.);
-        spawn_enemy(
-            commands,
-            meshes,
-            materials,
-            enemy_scene,
-            Enemy::FrijolAmarillo,
-            pos,
-        );
-    }
-}
-
-fn update_score(mut score: ResMut<Score>, second_timer: Res<SecondTimer>) {
-    if second_timer.0.just_finished() {
-        score.0 += 10;
-    }
-}
+const PLAYER_HEIGHT: Scalar = 2.0;
+const PLAYER_CROUCH_HEIGHT: Scalar = 0.8;
+const PLAYER_RADIUS: Scalar = 0.5;
 
-fn spawn_enemy(
-    mut commands: Commands,
-    _meshes: ResMut<Assets<Mesh>>,
-    _materials: ResMut<Assets<StandardMaterial>>,
-    enemy_scene: Res<EnemyModel>,
-    enemy: Enemy,
-    transform: Transform,
-) {
-    match enemy {
-        Enemy::FrijolAmarillo => commands.spawn((
-            RigidBody::Dynamic,
-            Collider::capsule(0.05, 0.05),
-            CollisionLayers::new([Layer::Enemy], [Layer::Player]),
-            LinearVelocity(Vec3::new(-1., 0., 0.)),
-            Enemy::FrijolRojo,
-            SceneBundle {
-                scene: enemy_scene.amarillo.clone(),
-                transform,
-                ..default()
-            },
-        )),
-        _ => unimplemented!(),
-    };
-}
-
-fn despawn_nonvisible_enemies(
-    mut commands: Commands,
-    enemies: Query<(Entity, &ViewVisibility), With<Enemy>>,
-) {
-    for (entity, visibility) in &enemies {
-        if !visibility.get() {
-            commands.entity(entity).despawn_recursive();
-        }
-    }
-}
+/// Minimum upward component of the contact normal, pointing from the enemy
+/// to the player, for a collision to count as a stomp.
+const STOMP_MIN_NORMAL_Y: Scalar = 0.5;
 
 fn handle_collisions(
     mut collision_event_reader: EventReader<Collision>,
     mut commands: Commands,
-    enemy_query: Query<Entity, With<Enemy>>,
-    player_query: Query<Entity, With<Player>>,
-    mut next_state: ResMut<NextState<GameState>>,
+    health_rules: Res<HealthRules>,
+    scoring_rules: Res<ScoringRules>,
+    mut power_ups: ResMut<ActivePowerUps>,
+    mut score_events: EventWriter<ScoreEvent>,
+    enemy_query: Query<(&Transform, &Rotation), With<Enemy>>,
+    mut player_query: Query<
+        (
+            &Transform,
+            &JumpImpulse,
+            &mut Health,
+            &mut LinearVelocity,
+            Has<Invulnerable>,
+        ),
+        With<Player>,
+    >,
 ) {
     for Collision(contacts) in collision_event_reader.read() {
-        let entities = [contacts.entity1, contacts.entity2];
-        let enemy = entities.iter().filter_map(|&e| enemy_query.get(e).ok());
-        let player = entities.iter().filter_map(|&e| player_query.get(e).ok());
-        for (enemy, _) in enemy.zip(player) {
-            commands.entity(enemy).despawn_recursive();
-            next_state.set(GameState::Menu);
+        let Some(manifold) = contacts.manifolds.first() else {
+            continue;
+        };
+        // Contact normals point out of their own entity, so towards the player here
+        let (enemy, player, enemy_transform, normal) =
+            if let Ok((transform, rotation)) = enemy_query.get(contacts.entity1) {
+                let normal = manifold.global_normal1(rotation);
+                (contacts.entity1, contacts.entity2, transform, normal)
+            } else if let Ok((transform, rotation)) = enemy_query.get(contacts.entity2) {
+                let normal = manifold.global_normal2(rotation);
+                (contacts.entity2, contacts.entity1, transform, normal)
+            } else {
+                continue;
+            };
+        let Ok((transform, jump_impulse, mut health, mut linear_velocity, invulnerable)) =
+            player_query.get_mut(player)
+        else {
+            continue;
+        };
+        // Enemies pass through the player while the death animation plays
+        if health.is_dead() {
+            continue;
         }
-    }
-}
 
-// Once the scene is loaded, start the animation
-fn setup_scene_once_loaded(
-    animations: Res<Animations>,
-    mut anim_players: Query<&mut AnimationPlayer, Added<AnimationPlayer>>,
-) {
-    for mut anim_player in &mut anim_players {
-        anim_player.play(animations.0[0].clone_weak()).repeat();
-    }
-}
+        // The kinematic collision response has already stopped the fall when
+        // landing on the enemy, so a vertical velocity of zero still counts.
+        if normal.y >= STOMP_MIN_NORMAL_Y && linear_velocity.y <= 0.0 {
+            squash_enemy(&mut commands.entity(enemy));
+            linear_velocity.y = jump_impulse.0;
+            score_events.send(ScoreEvent {
+                source: ScoreSource::Stomp,
+                points: scoring_rules.stomp_bonus,
+                position: enemy_transform.translation,
+            });
+            continue;
+        }
 
-fn add_background(mut commands: Commands, asset_background: Res<AssetBackground>) {
-    commands.insert_resource(BackgroundImg(asset_background.0.clone()));
+        if invulnerable {
+            continue;
+        }
+        commands.entity(enemy).despawn_recursive();
+        let shielded = power_ups.consume(PowerUp::Shield);
+        if !shielded {
+            health.current = health.current.saturating_sub(1);
+        }
+        if health.is_dead() {
+            commands
+                .entity(player)
+                .insert(Dying::new(health_rules.death_duration));
+            return;
+        }
+
+        // Push the player away from the enemy
+        let direction = (transform.translation.x - enemy_transform.translation.x).signum();
+        linear_velocity.x = direction * health_rules.knockback.x;
+        linear_velocity.y = health_rules.knockback.y;
+        commands
+            .entity(player)
+            .insert(Invulnerable::new(health_rules.invulnerability));
+        // Only one hit per frame, the next ones are covered by the invulnerability
+        return;
+    }
 }
 
 fn add_ost(mut commands: Commands, asset_ost: Res<AssetOST>) {
@@ -0,0 +1,132 @@
+use crate::*;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::app::AppExit;
+use bevy_egui::{
+    egui::{self, Align2, Color32, FontId, RichText},
+    EguiContexts,
+};
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Menu), spawn_menu_camera)
+            .add_systems(Update, menu_ui.run_if(in_state(GameState::Menu)));
+    }
+}
+
+fn spawn_menu_camera(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), StateScoped(GameState::Menu)));
+}
+
+fn menu_ui(
+    mut contexts: EguiContexts,
+    score: Res<Score>,
+    last_run_lost: Res<LastRunLost>,
+    mut high_scores: ResMut<HighScores>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut settings_screen: ResMut<SettingsScreen>,
+    #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<AppExit>,
+) {
+    // Runs left through the pause menu go back to the title screen
+    let game_over = last_run_lost.0;
+    // Don't start a run when enter is pressed to confirm the name
+    let typing = contexts.ctx_mut().wants_keyboard_input();
+    let (title, play) = if game_over {
+        ("Game Over", "Play again")
+    } else {
+        ("Frijoles", "Play")
+    };
+
+    egui::Area::new("menu")
+        .anchor(Align2::CENTER_CENTER, (0., 0.))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    RichText::new(title)
+                        .color(Color32::WHITE)
+                        .font(FontId::proportional(96.0)),
+                );
+                if game_over {
+                    let Score(score) = *score;
+                    ui.label(
+                        RichText::new(format!("Score: {score}"))
+                            .color(Color32::WHITE)
+                            .font(FontId::proportional(48.0)),
+                    );
+                }
+                ui.add_space(32.);
+
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Name:").font(FontId::proportional(24.0)));
+                    let name = ui.text_edit_singleline(&mut high_scores.player_name);
+                    if name.lost_focus() {
+                        high_scores.save();
+                    }
+                });
+                ui.add_space(16.);
+
+                let play_button = ui.button(RichText::new(play).font(FontId::proportional(36.0)));
+                if play_button.clicked()
+                    || (!typing
+                        && !settings_screen.open
+                        && keyboard_input.just_pressed(KeyCode::Return))
+                {
+                    next_state.set(GameState::InGame);
+                }
+                if ui
+                    .button(RichText::new("Settings").font(FontId::proportional(36.0)))
+                    .clicked()
+                {
+                    settings_screen.open = true;
+                }
+
+                // There is nothing to quit to in the browser
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui
+                    .button(RichText::new("Quit").font(FontId::proportional(36.0)))
+                    .clicked()
+                {
+                    exit.send(AppExit);
+                }
+
+                if !high_scores.entries.is_empty() {
+                    ui.add_space(32.);
+                    high_score_table(ui, &high_scores, game_over);
+                }
+            });
+        });
+}
+
+fn high_score_table(ui: &mut egui::Ui, high_scores: &HighScores, game_over: bool) {
+    egui::Grid::new("high_scores")
+        .num_columns(5)
+        .spacing((24., 4.))
+        .show(ui, |ui| {
+            for (rank, entry) in high_scores.entries.iter().enumerate() {
+                // Highlight the run that just ended
+                let color = if game_over && high_scores.last_rank == Some(rank) {
+                    Color32::YELLOW
+                } else {
+                    Color32::WHITE
+                };
+                let seconds = entry.duration as u32;
+                let cells = [
+                    format!("{}.", rank + 1),
+                    entry.name.clone(),
+                    entry.score.to_string(),
+                    format!("{}:{:02}", seconds / 60, seconds % 60),
+                    entry.date.to_string(),
+                ];
+                for cell in cells {
+                    ui.label(
+                        RichText::new(cell)
+                            .color(color)
+                            .font(FontId::proportional(24.0)),
+                    );
+                }
+                ui.end_row();
+            }
+        });
+}
@@ -0,0 +1,199 @@
+use crate::*;
+use bevy::window::WindowFocused;
+use bevy_egui::{
+    egui::{self, Align2, Color32, FontId, RichText},
+    EguiContexts,
+};
+
+/// Whether a run is paused. Only meaningful while in [`GameState::InGame`].
+#[derive(States, Clone, Copy, Eq, PartialEq, Debug, Hash, Default)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        // Only a tab hidden during a running game should pause it
+        #[cfg(target_arch = "wasm32")]
+        app.add_systems(Startup, page_visibility::listen)
+            .add_systems(OnEnter(GameState::InGame), page_visibility::clear)
+            .add_systems(OnExit(PauseState::Paused), page_visibility::clear);
+
+        app.add_state::<PauseState>()
+            .configure_sets(
+                Update,
+                CharacterControllerSet.run_if(in_state(PauseState::Running)),
+            )
+            .add_systems(OnEnter(PauseState::Paused), pause_game)
+            .add_systems(OnExit(PauseState::Paused), resume_game)
+            .add_systems(OnExit(GameState::InGame), leave_pause)
+            .add_systems(OnEnter(GameState::Restart), restart)
+            .add_systems(
+                Update,
+                (
+                    toggle_pause,
+                    pause_on_focus_lost.run_if(in_state(PauseState::Running)),
+                    pause_menu_ui.run_if(in_state(PauseState::Paused)),
+                )
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+fn toggle_pause(
+    actions: Res<ActionState>,
+    settings_screen: Res<SettingsScreen>,
+    state: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    // Escape cancels rebinding a key instead
+    if settings_screen.open {
+        return;
+    }
+    if !actions.just_pressed(InputAction::Pause) {
+        return;
+    }
+    next_state.set(match state.get() {
+        PauseState::Running => PauseState::Paused,
+        PauseState::Paused => PauseState::Running,
+    });
+}
+
+/// Pauses when the window loses focus or, on the web, when the tab is hidden,
+/// so that players don't come back to a lost run.
+fn pause_on_focus_lost(
+    mut focus_events: EventReader<WindowFocused>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    let focus_lost = focus_events.read().any(|event| !event.focused);
+    #[cfg(target_arch = "wasm32")]
+    let focus_lost = focus_lost | page_visibility::take_hidden();
+
+    if focus_lost {
+        next_state.set(PauseState::Paused);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod page_visibility {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+    fn document() -> Option<web_sys::Document> {
+        web_sys::window()?.document()
+    }
+
+    /// Starts listening to `visibilitychange` events on the page.
+    pub fn listen() {
+        let Some(document) = document() else {
+            return;
+        };
+        let callback = Closure::<dyn FnMut()>::new(|| {
+            if document().is_some_and(|document| document.hidden()) {
+                HIDDEN.store(true, Ordering::Relaxed);
+            }
+        });
+        let _ = document.add_event_listener_with_callback(
+            "visibilitychange",
+            callback.as_ref().unchecked_ref(),
+        );
+        // The listener stays registered for as long as the page is open
+        callback.forget();
+    }
+
+    /// Whether the page was hidden since the last call.
+    pub fn take_hidden() -> bool {
+        HIDDEN.swap(false, Ordering::Relaxed)
+    }
+
+    /// Forgets that the page was hidden.
+    pub fn clear() {
+        HIDDEN.store(false, Ordering::Relaxed);
+    }
+}
+
+fn pause_game(
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut physics_time: ResMut<Time<Physics>>,
+    music: Query<&AudioSink, With<Music>>,
+) {
+    virtual_time.pause();
+    physics_time.pause();
+    for sink in &music {
+        sink.pause();
+    }
+}
+
+fn resume_game(
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut physics_time: ResMut<Time<Physics>>,
+    music: Query<&AudioSink, With<Music>>,
+) {
+    virtual_time.unpause();
+    physics_time.unpause();
+    for sink in &music {
+        sink.play();
+    }
+}
+
+/// A run can't stay paused once it is over.
+fn leave_pause(mut next_state: ResMut<NextState<PauseState>>) {
+    next_state.set(PauseState::Running);
+}
+
+fn restart(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::InGame);
+}
+
+fn pause_menu_ui(
+    mut contexts: EguiContexts,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut settings_screen: ResMut<SettingsScreen>,
+) {
+    let ctx = contexts.ctx_mut();
+    egui::Area::new("pause_background")
+        .fixed_pos((0., 0.))
+        .interactable(false)
+        .show(ctx, |ui| {
+            let screen = ui.ctx().screen_rect();
+            ui.painter()
+                .rect_filled(screen, 0., Color32::from_black_alpha(160));
+        });
+
+    egui::Area::new("pause_menu")
+        .anchor(Align2::CENTER_CENTER, (0., 0.))
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    RichText::new("Paused")
+                        .color(Color32::WHITE)
+                        .font(FontId::proportional(96.0)),
+                );
+                ui.add_space(32.);
+
+                let button = |ui: &mut egui::Ui, text: &str| {
+                    ui.button(RichText::new(text).font(FontId::proportional(36.0)))
+                        .clicked()
+                };
+                if button(ui, "Resume") {
+                    next_pause_state.set(PauseState::Running);
+                }
+                if button(ui, "Settings") {
+                    settings_screen.open = true;
+                }
+                if button(ui, "Restart") {
+                    next_game_state.set(GameState::Restart);
+                }
+                if button(ui, "Quit to menu") {
+                    next_game_state.set(GameState::Menu);
+                }
+            });
+        });
+}
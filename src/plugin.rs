@@ -1,23 +1,34 @@
+use crate::{ActionState, Dying, InputAction, Layer};
 use bevy::{ecs::query::Has, prelude::*};
 use bevy_xpbd_3d::{math::*, prelude::*, SubstepSchedule, SubstepSet};
 
 pub struct CharacterControllerPlugin;
 
+/// The systems reading input and moving character controllers every frame.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CharacterControllerSet;
+
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<MovementAction>()
             .add_systems(
                 Update,
                 (
-                    keyboard_input,
-                    gamepad_input,
+                    // The player can't move while dying
+                    action_input.run_if(not(any_with_component::<Dying>())),
                     update_grounded,
                     apply_deferred,
+                    update_jump_timers,
+                    refill_air_jumps,
                     apply_gravity,
                     movement,
+                    dash,
+                    fast_fall,
+                    crouch,
                     apply_movement_damping,
                 )
-                    .chain(),
+                    .chain()
+                    .in_set(CharacterControllerSet),
             )
             .add_systems(
                 // Run collision handling in substep schedule
@@ -32,6 +43,13 @@ impl Plugin for CharacterControllerPlugin {
 pub enum MovementAction {
     Move(Vector2),
     Jump,
+    /// The jump button was let go.
+    JumpReleased,
+    Dash,
+    /// Sent every frame the down button is held.
+    FastFall,
+    /// Sent every frame the down button is held.
+    Crouch,
 }
 
 /// A marker component indicating that an entity is using a character controller.
@@ -52,7 +70,152 @@ pub struct MovementDampingFactor(Scalar);
 
 /// The strength of a jump.
 #[derive(Component)]
-pub struct JumpImpulse(Scalar);
+pub struct JumpImpulse(pub Scalar);
+
+/// Makes jumps lower when the jump button is released early, by multiplying the
+/// upward velocity by this factor.
+#[derive(Component)]
+pub struct VariableJumpHeight(pub Scalar);
+
+/// Remembers a jump pressed shortly before landing, so that it happens on
+/// landing instead of being lost.
+#[derive(Component)]
+pub struct JumpBuffer {
+    /// Seconds a jump press is remembered for.
+    pub window: Scalar,
+    remaining: Scalar,
+}
+
+impl JumpBuffer {
+    pub fn new(window: Scalar) -> Self {
+        Self {
+            window,
+            remaining: 0.0,
+        }
+    }
+}
+
+/// Still allows jumping for a short time after walking off an edge.
+#[derive(Component)]
+pub struct CoyoteTime {
+    /// Seconds after leaving the ground during which a jump is allowed.
+    pub window: Scalar,
+    remaining: Scalar,
+}
+
+impl CoyoteTime {
+    pub fn new(window: Scalar) -> Self {
+        Self {
+            window,
+            remaining: 0.0,
+        }
+    }
+}
+
+/// Jumps that can be made in the air before landing again.
+#[derive(Component)]
+pub struct AirJumps {
+    pub max: u32,
+    remaining: u32,
+}
+
+impl AirJumps {
+    pub fn new(max: u32) -> Self {
+        Self {
+            max,
+            remaining: max,
+        }
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+}
+
+/// A quick horizontal burst in the direction the character last moved.
+#[derive(Component)]
+pub struct Dash {
+    pub speed: Scalar,
+    pub cooldown: Timer,
+    /// Sign of the last horizontal movement.
+    facing: Scalar,
+}
+
+impl Dash {
+    pub fn new(speed: Scalar, cooldown: f32) -> Self {
+        let mut cooldown = Timer::from_seconds(cooldown, TimerMode::Once);
+        // Available right away
+        cooldown.tick(cooldown.duration());
+        Self {
+            speed,
+            cooldown,
+            facing: 1.0,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.cooldown.finished()
+    }
+}
+
+/// Lets the character drop down quickly while in the air.
+#[derive(Component)]
+pub struct FastFall {
+    /// Downward speed the character falls at, at least.
+    pub speed: Scalar,
+}
+
+/// Lets the character crouch under low obstacles, or slide when crouching
+/// while moving.
+///
+/// Crouching swaps the collider and ground caster for shorter ones that keep
+/// the same bottom, so the character doesn't drop when crouching.
+#[derive(Component)]
+pub struct Crouch {
+    standing_collider: Collider,
+    crouching_collider: Collider,
+    standing_caster: Collider,
+    crouching_caster: Collider,
+    /// How far the center of the crouching capsule is below the standing one.
+    offset: Scalar,
+    /// Used instead of [`MovementDampingFactor`] while crouching on the ground,
+    /// so that a slide keeps its momentum for longer.
+    pub slide_damping: Scalar,
+}
+
+impl Crouch {
+    /// Crouching for a `Collider::capsule(standing_height, radius)`.
+    pub fn capsule(
+        standing_height: Scalar,
+        crouching_height: Scalar,
+        radius: Scalar,
+        slide_damping: Scalar,
+    ) -> Self {
+        let offset = (standing_height - crouching_height) / 2.0;
+        let caster = |height| {
+            let mut caster = Collider::capsule(height, radius);
+            caster.set_scale(Vector::ONE * 0.99, 10);
+            caster
+        };
+        Self {
+            standing_collider: Collider::capsule(standing_height, radius),
+            crouching_collider: Collider::compound(vec![(
+                Vector::NEG_Y * offset,
+                Quaternion::default(),
+                Collider::capsule(crouching_height, radius),
+            )]),
+            standing_caster: caster(standing_height),
+            crouching_caster: caster(crouching_height),
+            offset,
+            slide_damping,
+        }
+    }
+}
+
+/// A marker component indicating that a character is crouching.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Crouching;
 
 /// The gravitational acceleration used for a character controller.
 #[derive(Component)]
@@ -141,61 +304,27 @@ impl CharacterControllerBundle {
     }
 }
 
-/// Sends [`MovementAction`] events based on keyboard input.
-fn keyboard_input(
-    mut movement_event_writer: EventWriter<MovementAction>,
-    keyboard_input: Res<Input<KeyCode>>,
-) {
-    //let up = keyboard_input.any_pressed([KeyCode::W, KeyCode::Up]);
-    //let down = keyboard_input.any_pressed([KeyCode::S, KeyCode::Down]);
-    let left = keyboard_input.any_pressed([KeyCode::A, KeyCode::Left]);
-    let right = keyboard_input.any_pressed([KeyCode::D, KeyCode::Right]);
-
-    let horizontal = right as i8 - left as i8;
-    //let vertical = up as i8 - down as i8;
-    let vertical = 0;
-    let direction = Vector2::new(horizontal as Scalar, vertical as Scalar).clamp_length_max(1.0);
+/// Sends [`MovementAction`] events based on the bound input actions.
+fn action_input(mut movement_event_writer: EventWriter<MovementAction>, actions: Res<ActionState>) {
+    let horizontal = actions.value(InputAction::MoveRight) - actions.value(InputAction::MoveLeft);
+    let direction = Vector2::new(horizontal as Scalar, 0.).clamp_length_max(1.0);
 
     if direction != Vector2::ZERO {
         movement_event_writer.send(MovementAction::Move(direction));
     }
 
-    if keyboard_input.just_pressed(KeyCode::Space) {
+    if actions.just_pressed(InputAction::Jump) {
         movement_event_writer.send(MovementAction::Jump);
     }
-}
-
-/// Sends [`MovementAction`] events based on gamepad input.
-fn gamepad_input(
-    mut movement_event_writer: EventWriter<MovementAction>,
-    gamepads: Res<Gamepads>,
-    axes: Res<Axis<GamepadAxis>>,
-    buttons: Res<Input<GamepadButton>>,
-) {
-    for gamepad in gamepads.iter() {
-        let axis_lx = GamepadAxis {
-            gamepad,
-            axis_type: GamepadAxisType::LeftStickX,
-        };
-        let axis_ly = GamepadAxis {
-            gamepad,
-            axis_type: GamepadAxisType::LeftStickY,
-        };
-
-        if let (Some(x), Some(y)) = (axes.get(axis_lx), axes.get(axis_ly)) {
-            movement_event_writer.send(MovementAction::Move(
-                Vector2::new(x as Scalar, y as Scalar).clamp_length_max(1.0),
-            ));
-        }
-
-        let jump_button = GamepadButton {
-            gamepad,
-            button_type: GamepadButtonType::South,
-        };
-
-        if buttons.just_pressed(jump_button) {
-            movement_event_writer.send(MovementAction::Jump);
-        }
+    if actions.just_released(InputAction::Jump) {
+        movement_event_writer.send(MovementAction::JumpReleased);
+    }
+    if actions.just_pressed(InputAction::Dash) {
+        movement_event_writer.send(MovementAction::Dash);
+    }
+    if actions.pressed(InputAction::Down) {
+        movement_event_writer.send(MovementAction::FastFall);
+        movement_event_writer.send(MovementAction::Crouch);
     }
 }
 
@@ -226,6 +355,32 @@ fn update_grounded(
     }
 }
 
+/// Counts down [`JumpBuffer`] and [`CoyoteTime`], restarting coyote time
+/// while on the ground.
+fn update_jump_timers(
+    time: Res<Time>,
+    mut controllers: Query<(
+        Option<&mut JumpBuffer>,
+        Option<&mut CoyoteTime>,
+        Has<Grounded>,
+    )>,
+) {
+    let delta_time = time.delta_seconds_f64().adjust_precision();
+
+    for (jump_buffer, coyote_time, is_grounded) in &mut controllers {
+        if let Some(mut jump_buffer) = jump_buffer {
+            jump_buffer.remaining = (jump_buffer.remaining - delta_time).max(0.0);
+        }
+        if let Some(mut coyote_time) = coyote_time {
+            coyote_time.remaining = if is_grounded {
+                coyote_time.window
+            } else {
+                (coyote_time.remaining - delta_time).max(0.0)
+            };
+        }
+    }
+}
+
 /// Responds to [`MovementAction`] events and moves character controllers accordingly.
 fn movement(
     time: Res<Time>,
@@ -235,31 +390,136 @@ fn movement(
         &JumpImpulse,
         &mut LinearVelocity,
         Has<Grounded>,
+        Option<&VariableJumpHeight>,
+        Option<&mut JumpBuffer>,
+        Option<&mut CoyoteTime>,
+        Option<&mut AirJumps>,
+        Has<Crouching>,
     )>,
 ) {
     // Precision is adjusted so that the example works with
     // both the `f32` and `f64` features. Otherwise you don't need this.
     let delta_time = time.delta_seconds_f64().adjust_precision();
-
-    for event in movement_event_reader.read() {
-        for (movement_acceleration, jump_impulse, mut linear_velocity, is_grounded) in
-            &mut controllers
-        {
+    let events: Vec<&MovementAction> = movement_event_reader.read().collect();
+
+    for (
+        movement_acceleration,
+        jump_impulse,
+        mut linear_velocity,
+        is_grounded,
+        variable_jump_height,
+        mut jump_buffer,
+        mut coyote_time,
+        air_jumps,
+        is_crouching,
+    ) in &mut controllers
+    {
+        // A slide can't be steered
+        let is_sliding = is_crouching && is_grounded;
+        let mut wants_jump = false;
+        for event in &events {
             match event {
+                MovementAction::Move(_) if is_sliding => {}
                 MovementAction::Move(direction) => {
                     linear_velocity.x += direction.x * movement_acceleration.0 * delta_time;
                     linear_velocity.z -= direction.y * movement_acceleration.0 * delta_time;
                 }
-                MovementAction::Jump => {
-                    if is_grounded {
-                        linear_velocity.y = jump_impulse.0;
+                MovementAction::Jump => wants_jump = true,
+                MovementAction::JumpReleased => {
+                    if let Some(variable_jump_height) = variable_jump_height {
+                        if linear_velocity.y > 0.0 {
+                            linear_velocity.y *= variable_jump_height.0;
+                        }
                     }
                 }
+                MovementAction::Dash | MovementAction::FastFall | MovementAction::Crouch => {}
+            }
+        }
+        let pressed_jump = wants_jump;
+
+        // A buffered jump happens as soon as jumping is allowed
+        if let Some(jump_buffer) = jump_buffer.as_mut() {
+            if wants_jump {
+                jump_buffer.remaining = jump_buffer.window;
             }
+            wants_jump = jump_buffer.remaining > 0.0;
+        }
+        let can_jump = is_grounded || coyote_time.as_ref().is_some_and(|c| c.remaining > 0.0);
+
+        if wants_jump && can_jump {
+            linear_velocity.y = jump_impulse.0;
+            if let Some(jump_buffer) = jump_buffer.as_mut() {
+                jump_buffer.remaining = 0.0;
+            }
+            // Coyote time doesn't give a second jump in the air
+            if let Some(coyote_time) = coyote_time.as_mut() {
+                coyote_time.remaining = 0.0;
+            }
+        } else if pressed_jump {
+            // Only an actual press uses up an air jump, not a buffered one
+            if let Some(mut air_jumps) = air_jumps.filter(|air_jumps| air_jumps.remaining > 0) {
+                linear_velocity.y = jump_impulse.0;
+                air_jumps.remaining -= 1;
+                if let Some(jump_buffer) = jump_buffer.as_mut() {
+                    jump_buffer.remaining = 0.0;
+                }
+            }
+        }
+    }
+}
+
+/// Gives back every [`AirJumps`] once on the ground.
+fn refill_air_jumps(mut controllers: Query<&mut AirJumps, With<Grounded>>) {
+    for mut air_jumps in &mut controllers {
+        if air_jumps.remaining != air_jumps.max {
+            air_jumps.remaining = air_jumps.max;
         }
     }
 }
 
+/// Responds to [`MovementAction::Dash`] for characters that can [`Dash`].
+fn dash(
+    time: Res<Time>,
+    mut movement_event_reader: EventReader<MovementAction>,
+    mut controllers: Query<(&mut Dash, &mut LinearVelocity)>,
+) {
+    let events: Vec<&MovementAction> = movement_event_reader.read().collect();
+
+    for (mut dash, mut linear_velocity) in &mut controllers {
+        dash.cooldown.tick(time.delta());
+        for event in &events {
+            match event {
+                MovementAction::Move(direction) if direction.x != 0.0 => {
+                    dash.facing = direction.x.signum();
+                }
+                MovementAction::Dash if dash.is_ready() => {
+                    linear_velocity.x = dash.facing * dash.speed;
+                    // Dashing stops a fall, so it can be used to cross gaps in the air
+                    linear_velocity.y = linear_velocity.y.max(0.0);
+                    dash.cooldown.reset();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Responds to [`MovementAction::FastFall`] for airborne characters that can [`FastFall`].
+fn fast_fall(
+    mut movement_event_reader: EventReader<MovementAction>,
+    mut controllers: Query<(&FastFall, &mut LinearVelocity), Without<Grounded>>,
+) {
+    let falling_fast = movement_event_reader
+        .read()
+        .any(|event| matches!(event, MovementAction::FastFall));
+    if !falling_fast {
+        return;
+    }
+    for (fast_fall, mut linear_velocity) in &mut controllers {
+        linear_velocity.y = linear_velocity.y.min(-fast_fall.speed);
+    }
+}
+
 /// Applies [`ControllerGravity`] to character controllers.
 fn apply_gravity(
     time: Res<Time>,
@@ -274,12 +534,79 @@ fn apply_gravity(
     }
 }
 
+/// Responds to [`MovementAction::Crouch`] for characters that can [`Crouch`],
+/// standing back up once the button is released and there is room above.
+fn crouch(
+    mut commands: Commands,
+    mut movement_event_reader: EventReader<MovementAction>,
+    spatial_query: SpatialQuery,
+    mut controllers: Query<(
+        Entity,
+        &Crouch,
+        &mut Collider,
+        &mut ShapeCaster,
+        &Position,
+        &Rotation,
+        Has<Crouching>,
+    )>,
+) {
+    let wants_crouch = movement_event_reader
+        .read()
+        .any(|event| matches!(event, MovementAction::Crouch));
+
+    for (entity, crouch, mut collider, mut caster, position, rotation, is_crouching) in
+        &mut controllers
+    {
+        if wants_crouch && !is_crouching {
+            *collider = crouch.crouching_collider.clone();
+            caster.shape = crouch.crouching_caster.clone();
+            caster.origin = Vector::NEG_Y * crouch.offset;
+            commands.entity(entity).insert(Crouching);
+        } else if !wants_crouch && is_crouching {
+            // Sweep the crouching shape up to where the top of the standing one would be
+            let blocked = spatial_query
+                .cast_shape(
+                    &crouch.crouching_caster,
+                    position.0 + Vector::NEG_Y * crouch.offset,
+                    rotation.0,
+                    Vector::Y,
+                    crouch.offset * 2.0,
+                    true,
+                    // Only the level blocks standing up, not enemies or pickups
+                    SpatialQueryFilter::new()
+                        .with_masks([Layer::Ground])
+                        .without_entities([entity]),
+                )
+                .is_some();
+            if blocked {
+                continue;
+            }
+            *collider = crouch.standing_collider.clone();
+            caster.shape = crouch.standing_caster.clone();
+            caster.origin = Vector::ZERO;
+            commands.entity(entity).remove::<Crouching>();
+        }
+    }
+}
+
 /// Slows down movement in the XZ plane.
-fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearVelocity)>) {
-    for (damping_factor, mut linear_velocity) in &mut query {
+fn apply_movement_damping(
+    mut query: Query<(
+        &MovementDampingFactor,
+        &mut LinearVelocity,
+        Option<&Crouch>,
+        Has<Crouching>,
+        Has<Grounded>,
+    )>,
+) {
+    for (damping_factor, mut linear_velocity, crouch, is_crouching, is_grounded) in &mut query {
+        let damping = match crouch {
+            Some(crouch) if is_crouching && is_grounded => crouch.slide_damping,
+            _ => damping_factor.0,
+        };
         // We could use `LinearDamping`, but we don't want to dampen movement along the Y axis
-        linear_velocity.x *= damping_factor.0;
-        linear_velocity.z *= damping_factor.0;
+        linear_velocity.x *= damping;
+        linear_velocity.z *= damping;
     }
 }
 
@@ -0,0 +1,307 @@
+use crate::*;
+use rand::distributions::Uniform;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerUp {
+    /// Absorbs the next hit.
+    Shield,
+    /// Slows the whole game down.
+    SlowMotion,
+    /// Pulls nearby pickups towards the player.
+    Magnet,
+    /// Multiplies every point earned.
+    DoubleScore,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 4] = [
+        PowerUp::Shield,
+        PowerUp::SlowMotion,
+        PowerUp::Magnet,
+        PowerUp::DoubleScore,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerUp::Shield => "Shield",
+            PowerUp::SlowMotion => "Slow motion",
+            PowerUp::Magnet => "Magnet",
+            PowerUp::DoubleScore => "Double score",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            PowerUp::Shield => Color::CYAN,
+            PowerUp::SlowMotion => Color::PURPLE,
+            PowerUp::Magnet => Color::ORANGE_RED,
+            PowerUp::DoubleScore => Color::GOLD,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct PowerUpRules {
+    /// Seconds between two pickups.
+    pub spawn_interval: f32,
+    /// How far above the top of the arena pickups spawn.
+    pub spawn_offset: Scalar,
+    pub shield_duration: f32,
+    pub slow_motion_duration: f32,
+    /// Relative speed of the game while slowed down.
+    pub slow_motion_speed: f32,
+    pub magnet_duration: f32,
+    pub magnet_radius: Scalar,
+    pub magnet_speed: Scalar,
+    pub double_score_duration: f32,
+    pub score_multiplier: u32,
+}
+
+impl Default for PowerUpRules {
+    fn default() -> Self {
+        Self {
+            spawn_interval: 8.,
+            spawn_offset: 0.5,
+            shield_duration: 15.,
+            slow_motion_duration: 5.,
+            slow_motion_speed: 0.5,
+            magnet_duration: 10.,
+            magnet_radius: 5.,
+            magnet_speed: 6.,
+            double_score_duration: 10.,
+            score_multiplier: 2,
+        }
+    }
+}
+
+impl PowerUpRules {
+    pub fn duration(&self, power_up: PowerUp) -> f32 {
+        match power_up {
+            PowerUp::Shield => self.shield_duration,
+            PowerUp::SlowMotion => self.slow_motion_duration,
+            PowerUp::Magnet => self.magnet_duration,
+            PowerUp::DoubleScore => self.double_score_duration,
+        }
+    }
+}
+
+pub struct ActivePowerUp {
+    pub power_up: PowerUp,
+    /// Ticks in real time, so slow motion doesn't make itself last longer.
+    pub timer: Timer,
+}
+
+/// The effects currently applied to the player.
+#[derive(Resource, Default)]
+pub struct ActivePowerUps(pub Vec<ActivePowerUp>);
+
+impl ActivePowerUps {
+    pub fn is_active(&self, power_up: PowerUp) -> bool {
+        self.0.iter().any(|active| active.power_up == power_up)
+    }
+
+    /// Starts an effect, or restarts it if it was already active.
+    pub fn activate(&mut self, power_up: PowerUp, seconds: f32) {
+        self.0.retain(|active| active.power_up != power_up);
+        self.0.push(ActivePowerUp {
+            power_up,
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+        });
+    }
+
+    /// Ends an effect early, returning whether it was active.
+    pub fn consume(&mut self, power_up: PowerUp) -> bool {
+        let active = self.is_active(power_up);
+        self.0.retain(|active| active.power_up != power_up);
+        active
+    }
+
+    pub fn score_multiplier(&self, rules: &PowerUpRules) -> u32 {
+        if self.is_active(PowerUp::DoubleScore) {
+            rules.score_multiplier
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Resource)]
+struct PowerUpTimer(Timer);
+
+#[derive(Resource)]
+struct PowerUpAssets {
+    mesh: Handle<Mesh>,
+    materials: Vec<(PowerUp, Handle<StandardMaterial>)>,
+}
+
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        let rules = PowerUpRules::default();
+        app.insert_resource(PowerUpTimer(Timer::from_seconds(
+            rules.spawn_interval,
+            TimerMode::Repeating,
+        )))
+        .insert_resource(rules)
+        .init_resource::<ActivePowerUps>()
+        .add_systems(Startup, create_power_up_assets)
+        .add_systems(OnEnter(GameState::InGame), reset_power_up_timer)
+        .add_systems(OnExit(GameState::InGame), clear_power_ups)
+        .add_systems(
+            Update,
+            (
+                spawn_power_ups,
+                collect_power_ups,
+                tick_power_ups.run_if(in_state(PauseState::Running)),
+                apply_slow_motion,
+                attract_pickups,
+                despawn_fallen_pickups,
+            )
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+fn create_power_up_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mesh = meshes.add(Mesh::from(shape::UVSphere {
+        radius: 0.3,
+        ..default()
+    }));
+    let materials = PowerUp::ALL
+        .iter()
+        .map(|&power_up| {
+            let material = materials.add(StandardMaterial {
+                base_color: power_up.color(),
+                emissive: power_up.color(),
+                ..default()
+            });
+            (power_up, material)
+        })
+        .collect();
+    commands.insert_resource(PowerUpAssets { mesh, materials });
+}
+
+fn reset_power_up_timer(mut timer: ResMut<PowerUpTimer>) {
+    timer.0.reset();
+}
+
+fn clear_power_ups(mut active: ResMut<ActivePowerUps>, mut time: ResMut<Time<Virtual>>) {
+    active.0.clear();
+    time.set_relative_speed(1.);
+}
+
+fn spawn_power_ups(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<PowerUpRules>,
+    assets: Res<PowerUpAssets>,
+    bounds: Res<ArenaBounds>,
+    mut timer: ResMut<PowerUpTimer>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let mut rng = rand::thread_rng();
+    let Some((power_up, material)) = assets.materials.choose(&mut rng) else {
+        return;
+    };
+    let x: f32 = rng.sample(Uniform::new(bounds.min.x, bounds.max.x));
+
+    commands.spawn((
+        PbrBundle {
+            mesh: assets.mesh.clone(),
+            material: material.clone(),
+            transform: Transform::from_xyz(x, bounds.max.y + rules.spawn_offset, 0.),
+            ..default()
+        },
+        RigidBody::Dynamic,
+        Collider::ball(0.3),
+        Sensor,
+        GravityScale(0.3),
+        CollisionLayers::new([Layer::Pickup], [Layer::Player]),
+        *power_up,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+fn collect_power_ups(
+    mut commands: Commands,
+    mut collision_event_reader: EventReader<Collision>,
+    rules: Res<PowerUpRules>,
+    mut active: ResMut<ActivePowerUps>,
+    pickups: Query<&PowerUp>,
+    player_query: Query<(), With<Player>>,
+) {
+    for Collision(contacts) in collision_event_reader.read() {
+        let entities = [contacts.entity1, contacts.entity2];
+        if !entities.iter().any(|&e| player_query.contains(e)) {
+            continue;
+        }
+        for entity in entities {
+            if let Ok(&power_up) = pickups.get(entity) {
+                active.activate(power_up, rules.duration(power_up));
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn tick_power_ups(time: Res<Time<Real>>, mut active: ResMut<ActivePowerUps>) {
+    active
+        .0
+        .retain_mut(|active| !active.timer.tick(time.delta()).finished());
+}
+
+fn apply_slow_motion(
+    rules: Res<PowerUpRules>,
+    active: Res<ActivePowerUps>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    let speed = if active.is_active(PowerUp::SlowMotion) {
+        rules.slow_motion_speed
+    } else {
+        1.
+    };
+    if time.relative_speed() != speed {
+        time.set_relative_speed(speed);
+    }
+}
+
+fn attract_pickups(
+    rules: Res<PowerUpRules>,
+    active: Res<ActivePowerUps>,
+    player_query: Query<&Transform, With<Player>>,
+    mut pickups: Query<(&Transform, &mut LinearVelocity), With<PowerUp>>,
+) {
+    if !active.is_active(PowerUp::Magnet) {
+        return;
+    }
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    for (transform, mut linear_velocity) in &mut pickups {
+        let offset = player.translation - transform.translation;
+        if offset.length() <= rules.magnet_radius {
+            linear_velocity.0 = offset.normalize_or_zero() * rules.magnet_speed;
+        }
+    }
+}
+
+fn despawn_fallen_pickups(
+    mut commands: Commands,
+    bounds: Res<ArenaBounds>,
+    pickups: Query<(Entity, &Transform), With<PowerUp>>,
+) {
+    for (entity, transform) in &pickups {
+        if transform.translation.y < bounds.min.y - 1. {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
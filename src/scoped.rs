@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+
+/// Ties an entity to a state: it is despawned, along with its children,
+/// when the app exits that state.
+#[derive(Component, Clone, Debug)]
+pub struct StateScoped<S: States>(pub S);
+
+pub trait StateScopedAppExt {
+    /// Despawns every entity marked with [`StateScoped`] for `state` on [`OnExit`].
+    fn add_state_scoped<S: States>(&mut self, state: S) -> &mut Self;
+}
+
+impl StateScopedAppExt for App {
+    fn add_state_scoped<S: States>(&mut self, state: S) -> &mut Self {
+        let exited = state.clone();
+        self.add_systems(
+            OnExit(state),
+            move |mut commands: Commands, entities: Query<(Entity, &StateScoped<S>)>| {
+                for (entity, scope) in &entities {
+                    if scope.0 == exited {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            },
+        )
+    }
+}
@@ -0,0 +1,325 @@
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreSource {
+    /// Staying alive for another second.
+    Survival,
+    /// An enemy left the screen without hitting the player.
+    Dodge,
+    /// An enemy passed close to the player.
+    NearMiss,
+    /// The player landed on an enemy.
+    Stomp,
+}
+
+/// Sent by gameplay systems to request points.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ScoreEvent {
+    pub source: ScoreSource,
+    pub points: u32,
+    /// Where it happened, for the score popup.
+    pub position: Vec3,
+}
+
+/// Sent once a [`ScoreEvent`] has been added to the [`Score`], with the combo and
+/// power-ups applied.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PointsAwarded {
+    pub source: ScoreSource,
+    pub points: u32,
+    pub position: Vec3,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct ScoringRules {
+    pub survival_points: u32,
+    /// Distance from the player under which an enemy that then escapes counts as
+    /// a near miss.
+    pub near_miss_radius: Scalar,
+    pub near_miss_bonus: u32,
+    pub stomp_bonus: u32,
+    /// Added to the combo multiplier for every dodge, near miss or stomp.
+    pub combo_step: f32,
+    pub max_combo: f32,
+    /// How much the combo multiplier drops per second.
+    pub combo_decay: f32,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        Self {
+            survival_points: 10,
+            near_miss_radius: 1.,
+            near_miss_bonus: 20,
+            stomp_bonus: 50,
+            combo_step: 0.25,
+            max_combo: 4.,
+            combo_decay: 0.5,
+        }
+    }
+}
+
+/// Multiplier applied to every point that isn't earned just by surviving.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Combo(pub f32);
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Marks enemies that came close to the player, with where it happened. They
+/// give a near miss bonus if they escape without hitting the player.
+#[derive(Component)]
+pub struct NearMissed(pub Vec3);
+
+pub struct ScoringPlugin;
+
+impl Plugin for ScoringPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ScoreEvent>()
+            .add_event::<PointsAwarded>()
+            .init_resource::<ScoringRules>()
+            .init_resource::<Combo>()
+            .add_systems(OnEnter(GameState::InGame), reset_combo)
+            .add_systems(
+                Update,
+                (
+                    (
+                        score_survival,
+                        score_dodges,
+                        detect_near_misses,
+                        decay_combo,
+                    ),
+                    award_points,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+fn reset_combo(mut combo: ResMut<Combo>) {
+    *combo = Combo::default();
+}
+
+fn score_survival(
+    second_timer: Res<SecondTimer>,
+    rules: Res<ScoringRules>,
+    player_query: Query<&Transform, With<Player>>,
+    mut score_events: EventWriter<ScoreEvent>,
+) {
+    if !second_timer.0.just_finished() {
+        return;
+    }
+    let position = player_query
+        .get_single()
+        .map_or(Vec3::ZERO, |transform| transform.translation);
+    score_events.send(ScoreEvent {
+        source: ScoreSource::Survival,
+        points: rules.survival_points,
+        position,
+    });
+}
+
+fn score_dodges(
+    rules: Res<ScoringRules>,
+    mut escaped_events: EventReader<EnemyEscaped>,
+    mut score_events: EventWriter<ScoreEvent>,
+) {
+    for escaped in escaped_events.read() {
+        if let Some(position) = escaped.near_miss {
+            score_events.send(ScoreEvent {
+                source: ScoreSource::NearMiss,
+                points: rules.near_miss_bonus,
+                position,
+            });
+        }
+        // Enemies running out of time never left the screen
+        if escaped.reason == EscapeReason::OutOfBounds {
+            score_events.send(ScoreEvent {
+                source: ScoreSource::Dodge,
+                points: escaped.reward,
+                position: escaped.position,
+            });
+        }
+    }
+}
+
+fn detect_near_misses(
+    mut commands: Commands,
+    rules: Res<ScoringRules>,
+    player_query: Query<&Transform, With<Player>>,
+    enemies: Query<(Entity, &Transform), (With<Enemy>, Without<NearMissed>)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    for (entity, transform) in &enemies {
+        if transform.translation.distance(player.translation) <= rules.near_miss_radius {
+            commands
+                .entity(entity)
+                .insert(NearMissed(transform.translation));
+        }
+    }
+}
+
+fn decay_combo(time: Res<Time>, rules: Res<ScoringRules>, mut combo: ResMut<Combo>) {
+    combo.0 = (combo.0 - rules.combo_decay * time.delta_seconds()).max(1.);
+}
+
+fn award_points(
+    rules: Res<ScoringRules>,
+    power_up_rules: Res<PowerUpRules>,
+    power_ups: Res<ActivePowerUps>,
+    mut score: ResMut<Score>,
+    mut combo: ResMut<Combo>,
+    mut score_events: EventReader<ScoreEvent>,
+    mut awarded: EventWriter<PointsAwarded>,
+) {
+    for event in score_events.read() {
+        let points = match event.source {
+            ScoreSource::Survival => event.points,
+            _ => {
+                let points = (event.points as f32 * combo.0).round() as u32;
+                combo.0 = (combo.0 + rules.combo_step).min(rules.max_combo);
+                points
+            }
+        };
+        let points = points * power_ups.score_multiplier(&power_up_rules);
+        score.0 += points;
+        awarded.send(PointsAwarded {
+            source: event.source,
+            points,
+            position: event.position,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    fn app() -> App {
+        let mut app = headless_app();
+        app.add_event::<EnemyEscaped>()
+            .add_event::<ScoreEvent>()
+            .add_event::<PointsAwarded>()
+            .init_resource::<ScoringRules>()
+            .init_resource::<PowerUpRules>()
+            .init_resource::<ActivePowerUps>()
+            .init_resource::<Score>()
+            .init_resource::<Combo>()
+            .add_systems(Update, ((score_dodges, decay_combo), award_points).chain());
+        app
+    }
+
+    fn send(app: &mut App, source: ScoreSource, points: u32) {
+        app.world.send_event(ScoreEvent {
+            source,
+            points,
+            position: Vec3::ZERO,
+        });
+    }
+
+    fn awarded(app: &App) -> Vec<(ScoreSource, u32)> {
+        events::<PointsAwarded>(app)
+            .into_iter()
+            .map(|event| (event.source, event.points))
+            .collect()
+    }
+
+    #[test]
+    fn combo_multiplies_and_grows_with_every_bonus() {
+        let mut app = app();
+        send(&mut app, ScoreSource::Dodge, 10);
+        send(&mut app, ScoreSource::NearMiss, 10);
+        send(&mut app, ScoreSource::Stomp, 10);
+        // The first update has no delta, so the combo doesn't decay
+        app.update();
+
+        // x1, then x1.25 rounded, then x1.5
+        assert_eq!(
+            awarded(&app),
+            [
+                (ScoreSource::Dodge, 10),
+                (ScoreSource::NearMiss, 13),
+                (ScoreSource::Stomp, 15),
+            ]
+        );
+        assert_eq!(app.world.resource::<Score>().0, 38);
+        assert_eq!(app.world.resource::<Combo>().0, 1.75);
+    }
+
+    #[test]
+    fn survival_ignores_the_combo() {
+        let mut app = app();
+        app.world.resource_mut::<Combo>().0 = 2.;
+        send(&mut app, ScoreSource::Survival, 10);
+        app.update();
+
+        assert_eq!(awarded(&app), [(ScoreSource::Survival, 10)]);
+        assert_eq!(app.world.resource::<Combo>().0, 2.);
+    }
+
+    #[test]
+    fn combo_stops_at_the_maximum() {
+        let mut app = app();
+        app.world.resource_mut::<Combo>().0 = 3.9;
+        send(&mut app, ScoreSource::Dodge, 10);
+        app.update();
+
+        assert_eq!(awarded(&app), [(ScoreSource::Dodge, 39)]);
+        assert_eq!(app.world.resource::<Combo>().0, 4.);
+    }
+
+    #[test]
+    fn combo_decays_down_to_one() {
+        let mut app = app();
+        app.update();
+        app.world.resource_mut::<Combo>().0 = 2.;
+        app.update();
+        // 0.5 per second for 100ms
+        assert_close(app.world.resource::<Combo>().0, 1.95);
+
+        app.world.resource_mut::<Combo>().0 = 1.02;
+        app.update();
+        assert_eq!(app.world.resource::<Combo>().0, 1.);
+    }
+
+    #[test]
+    fn escaped_enemies_give_dodge_and_near_miss_points() {
+        let mut app = app();
+        let escaped = |reason, near_miss| EnemyEscaped {
+            reason,
+            position: Vec3::ZERO,
+            reward: 5,
+            near_miss,
+        };
+        app.world
+            .send_event(escaped(EscapeReason::OutOfBounds, None));
+        app.world
+            .send_event(escaped(EscapeReason::OutOfBounds, Some(Vec3::X)));
+        // Expired enemies were dodged, but never left the screen
+        app.world
+            .send_event(escaped(EscapeReason::Expired, Some(Vec3::X)));
+        app.world.send_event(escaped(EscapeReason::Expired, None));
+        app.update();
+
+        let sources: Vec<ScoreSource> = awarded(&app)
+            .into_iter()
+            .map(|(source, _)| source)
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                ScoreSource::Dodge,
+                ScoreSource::NearMiss,
+                ScoreSource::Dodge,
+                ScoreSource::NearMiss,
+            ]
+        );
+    }
+}
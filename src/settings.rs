@@ -0,0 +1,174 @@
+use crate::*;
+use bevy::audio::Volume;
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode};
+use bevy_egui::{
+    egui::{self, Align2, FontId, RichText},
+    EguiContexts, EguiSettings,
+};
+use serde::{Deserialize, Serialize};
+
+const STORAGE_KEY: &str = "settings";
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub ui_scale: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.,
+            music_volume: 1.,
+            fullscreen: false,
+            vsync: true,
+            ui_scale: 1.,
+        }
+    }
+}
+
+impl Settings {
+    /// Volume to play the soundtrack at, relative to the global volume.
+    pub fn music_volume(&self) -> Volume {
+        Volume::new_relative(self.music_volume)
+    }
+}
+
+/// State of the settings screen, which can be opened from the menu and the pause menu.
+#[derive(Resource, Default)]
+pub struct SettingsScreen {
+    pub open: bool,
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // Loaded right away so it is applied before anything else starts
+        let settings = crate::storage::load::<Settings>(STORAGE_KEY).unwrap_or_default();
+        app.insert_resource(settings)
+            .init_resource::<SettingsScreen>()
+            .add_systems(
+                Update,
+                (
+                    settings_ui,
+                    apply_settings.run_if(resource_changed::<Settings>()),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut global_volume: ResMut<GlobalVolume>,
+    mut egui_settings: ResMut<EguiSettings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    music: Query<&AudioSink, With<Music>>,
+) {
+    // Only affects sounds started from now on, so running ones are updated below
+    *global_volume = GlobalVolume::new(settings.master_volume);
+    for sink in &music {
+        sink.set_volume(settings.master_volume * settings.music_volume);
+    }
+
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.mode = if settings.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+
+    egui_settings.scale_factor = settings.ui_scale as f64;
+}
+
+fn settings_ui(
+    mut contexts: EguiContexts,
+    mut screen: ResMut<SettingsScreen>,
+    mut settings: ResMut<Settings>,
+    mut input_map: ResMut<InputMap>,
+    default_input_map: Res<DefaultInputMap>,
+    mut rebinding: ResMut<Rebinding>,
+    mut gamepad_config: ResMut<GamepadConfig>,
+    gamepads: Res<Gamepads>,
+) {
+    if !screen.open {
+        return;
+    }
+    // Edit copies so that resources are only marked as changed when they are
+    let mut edited = settings.clone();
+    let mut edited_input_map = input_map.clone();
+    let mut edited_gamepad_config = gamepad_config.clone();
+    let mut close = false;
+
+    egui::Window::new(RichText::new("Settings").font(FontId::proportional(36.0)))
+        .anchor(Align2::CENTER_CENTER, (0., 0.))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("settings")
+                .num_columns(2)
+                .spacing((24., 8.))
+                .show(ui, |ui| {
+                    ui.label("Master volume");
+                    ui.add(egui::Slider::new(&mut edited.master_volume, 0.0..=1.0));
+                    ui.end_row();
+                    ui.label("Music volume");
+                    ui.add(egui::Slider::new(&mut edited.music_volume, 0.0..=1.0));
+                    ui.end_row();
+
+                    ui.label("Fullscreen");
+                    ui.checkbox(&mut edited.fullscreen, "");
+                    ui.end_row();
+                    ui.label("Vsync");
+                    ui.checkbox(&mut edited.vsync, "");
+                    ui.end_row();
+                    ui.label("Interface scale");
+                    ui.add(egui::Slider::new(&mut edited.ui_scale, 0.5..=2.0));
+                    ui.end_row();
+                });
+
+            ui.add_space(16.);
+            bindings_ui(ui, &mut edited_input_map, &mut rebinding);
+            ui.add_space(16.);
+            gamepad_ui(ui, &mut edited_gamepad_config, &gamepads);
+
+            ui.add_space(16.);
+            ui.horizontal(|ui| {
+                if ui.button("Reset to defaults").clicked() {
+                    edited = Settings::default();
+                    edited_input_map = default_input_map.0.clone();
+                    edited_gamepad_config = GamepadConfig::default();
+                    rebinding.cancel();
+                }
+                close = ui.button("Done").clicked();
+            });
+        });
+
+    if edited != *settings {
+        *settings = edited;
+    }
+    if edited_input_map != *input_map {
+        *input_map = edited_input_map;
+    }
+    if edited_gamepad_config != *gamepad_config {
+        *gamepad_config = edited_gamepad_config;
+    }
+    if close {
+        screen.open = false;
+        rebinding.cancel();
+        crate::storage::save(STORAGE_KEY, &*settings);
+        input_map.save();
+        gamepad_config.save();
+    }
+}
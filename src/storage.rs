@@ -0,0 +1,68 @@
+//! Small key-value persistence: RON files in the platform data directory on
+//! native, `localStorage` on the web.
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Reads the value saved under `key`, if there is one and it can be parsed.
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let text = read(key)?;
+    match ron::from_str(&text) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("could not parse saved {key}: {err}");
+            None
+        }
+    }
+}
+
+/// Saves `value` under `key`, logging a warning if it can't be written.
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    match ron::ser::to_string_pretty(value, default()) {
+        Ok(text) => write(key, &text),
+        Err(err) => warn!("could not serialize {key}: {err}"),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path(key: &str) -> Option<std::path::PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "gamejam2024")?;
+    Some(dirs.data_dir().join(format!("{key}.ron")))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read(key: &str) -> Option<String> {
+    std::fs::read_to_string(path(key)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(key: &str, text: &str) {
+    let Some(path) = path(key) else {
+        warn!("no data directory to save {key} in");
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, text));
+    if let Err(err) = result {
+        warn!("could not save {key} to {}: {err}", path.display());
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(key: &str, text: &str) {
+    let saved = local_storage().is_some_and(|storage| storage.set_item(key, text).is_ok());
+    if !saved {
+        warn!("could not save {key} to local storage");
+    }
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the tests of several modules.
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use std::time::Duration;
+
+/// How far time goes on every update of a [`headless_app`], but the first.
+pub const FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// An app without a window or rendering, where time advances by
+/// [`FRAME_TIME`] per update. The first update has no delta.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME));
+    app
+}
+
+/// Every event of type `E` sent during the last two updates.
+pub fn events<E: Event + Clone>(app: &App) -> Vec<E> {
+    let events = app.world.resource::<Events<E>>();
+    events.get_reader().read(events).cloned().collect()
+}
+
+pub fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-5,
+        "{actual} is not {expected}"
+    );
+}
@@ -0,0 +1,172 @@
+//! On-screen buttons for playing on phones and tablets, mostly in the browser.
+//!
+//! They show up once the screen is touched and hide again when a key is
+//! pressed. Every finger is handled on its own, so moving and jumping at the
+//! same time works.
+use crate::*;
+use bevy::input::InputSystem;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{
+    egui::{self, Align2, Color32, FontId},
+    EguiContexts, EguiSettings,
+};
+
+/// Size of a button, in logical pixels.
+const BUTTON_SIZE: f32 = 96.;
+const MARGIN: f32 = 24.;
+
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    pub visible: bool,
+}
+
+impl TouchControls {
+    /// How far HUD anchored to the top right corner has to move left to stay
+    /// clear of the pause button, in egui points.
+    pub fn top_right_inset(&self, egui_settings: &EguiSettings) -> f32 {
+        if self.visible {
+            (BUTTON_SIZE + MARGIN) / egui_settings.scale_factor as f32
+        } else {
+            0.
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TouchButton {
+    action: InputAction,
+    label: &'static str,
+    rect: Rect,
+}
+
+/// Where the buttons are on a window of the given logical size.
+fn layout(window_size: Vec2) -> [TouchButton; 6] {
+    let size = Vec2::splat(BUTTON_SIZE);
+    let left = MARGIN;
+    let right = window_size.x - MARGIN - BUTTON_SIZE;
+    let top = MARGIN;
+    let bottom = window_size.y - MARGIN - BUTTON_SIZE;
+    let button = |action, label, x: f32, y: f32| TouchButton {
+        action,
+        label,
+        rect: Rect::from_corners(Vec2::new(x, y), Vec2::new(x, y) + size),
+    };
+    [
+        button(InputAction::MoveLeft, "◀", left, bottom),
+        button(
+            InputAction::MoveRight,
+            "▶",
+            left + BUTTON_SIZE + MARGIN,
+            bottom,
+        ),
+        button(InputAction::Jump, "▲", right, bottom),
+        button(InputAction::Dash, "»", right - BUTTON_SIZE - MARGIN, bottom),
+        button(InputAction::Down, "▼", right, bottom - BUTTON_SIZE - MARGIN),
+        button(InputAction::Pause, "II", right, top),
+    ]
+}
+
+pub struct TouchPlugin;
+
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>()
+            .add_systems(PreUpdate, show_touch_controls.after(InputSystem))
+            .add_systems(
+                PreUpdate,
+                touch_input
+                    .after(update_action_state)
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(in_state(PauseState::Running)),
+            )
+            .add_systems(
+                Update,
+                touch_controls_ui
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(in_state(PauseState::Running)),
+            );
+    }
+}
+
+fn show_touch_controls(
+    touches: Res<Touches>,
+    keys: Res<Input<KeyCode>>,
+    mut controls: ResMut<TouchControls>,
+) {
+    if touches.any_just_pressed() {
+        controls.visible = true;
+    } else if keys.get_just_pressed().next().is_some() {
+        controls.visible = false;
+    }
+}
+
+fn touch_input(
+    touches: Res<Touches>,
+    controls: Res<TouchControls>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut actions: ResMut<ActionState>,
+) {
+    if !controls.visible {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let buttons = layout(Vec2::new(window.width(), window.height()));
+    for touch in touches.iter() {
+        for button in &buttons {
+            if button.rect.contains(touch.position()) {
+                actions.push(button.action, 1.);
+            }
+        }
+    }
+}
+
+fn touch_controls_ui(
+    mut contexts: EguiContexts,
+    controls: Res<TouchControls>,
+    touches: Res<Touches>,
+    egui_settings: Res<EguiSettings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    if !controls.visible {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    // egui points are logical pixels scaled by the interface scale
+    let scale = egui_settings.scale_factor as f32;
+    let to_egui = |rect: Rect| {
+        egui::Rect::from_min_max(
+            egui::pos2(rect.min.x / scale, rect.min.y / scale),
+            egui::pos2(rect.max.x / scale, rect.max.y / scale),
+        )
+    };
+
+    egui::Area::new("touch_controls")
+        .fixed_pos((0., 0.))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let painter = ui.painter();
+            for button in layout(Vec2::new(window.width(), window.height())) {
+                let held = touches
+                    .iter()
+                    .any(|touch| button.rect.contains(touch.position()));
+                let alpha = if held { 160 } else { 80 };
+                let rect = to_egui(button.rect);
+                painter.circle_filled(
+                    rect.center(),
+                    rect.width() / 2.,
+                    Color32::from_white_alpha(alpha),
+                );
+                painter.text(
+                    rect.center(),
+                    Align2::CENTER_CENTER,
+                    button.label,
+                    FontId::proportional(rect.height() / 2.),
+                    Color32::BLACK,
+                );
+            }
+        });
+}
@@ -51,7 +51,27 @@
 
     // We bind the resume function for each user interaction
     // event on the page
-    userInputEventNames.forEach(eventName => {
-        document.addEventListener(eventName, resumeAllContexts);
-    });
+    function bindUserInputEvents() {
+        userInputEventNames.forEach(eventName => {
+            document.addEventListener(eventName, resumeAllContexts);
+        });
+    }
+
+    bindUserInputEvents();
+
+    // Browsers may suspend audio while the tab is hidden or the window
+    // loses focus, so try to resume when coming back, and wait for the
+    // next user interaction if that isn't allowed yet
+    function resumeOnReturn() {
+        if (document.visibilityState !== 'visible') {
+            return;
+        }
+        if (audioContextList.some(context => context.state !== 'running')) {
+            bindUserInputEvents();
+            resumeAllContexts();
+        }
+    }
+
+    document.addEventListener('visibilitychange', resumeOnReturn);
+    window.addEventListener('focus', resumeOnReturn);
 })();